@@ -69,6 +69,8 @@ HTML=$(cat <<- END
 <html lang="en">
 <head>
     <meta charset="utf-8">
+    <!-- keep phones from zooming or scrolling the page while playing with the touch controls -->
+    <meta name="viewport" content="width=device-width, initial-scale=1, user-scalable=no">
     <title>${PROJECT_NAME}</title>
     <style>
         html,
@@ -81,6 +83,7 @@ HTML=$(cat <<- END
             overflow: hidden;
             position: absolute;
             z-index: 0;
+            touch-action: none;
         }
     </style>
 </head>
@@ -0,0 +1,72 @@
+//! Compares two desync dumps, usually one written by each peer, field by field and
+//! reports the first frame at which the states or inputs differ.
+
+use std::path::Path;
+use std::process::exit;
+
+use ggrs_demo::desync::{diff_inputs, diff_states, DesyncDump};
+
+const HELP_STRING: &str = "usage: desync_diff LOCAL_DUMP REMOTE_DUMP
+
+Compares the states and inputs of two desync dumps frame by frame and prints every field
+that differs. Exits with status 1 if the dumps differ.";
+
+fn load(path: &str) -> DesyncDump {
+    DesyncDump::load(Path::new(path)).unwrap_or_else(|e| {
+        eprintln!("Error: {path}: {e}");
+        exit(2);
+    })
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{HELP_STRING}");
+        exit(0);
+    }
+    let [a_path, b_path] = args.as_slice() else {
+        eprintln!("Error: expected two dump files\n\n{HELP_STRING}");
+        exit(2);
+    };
+    let a = load(a_path);
+    let b = load(b_path);
+
+    println!(
+        "{a_path}: desync on frame {}, local checksum {}, remote checksum {}",
+        a.frame, a.local_checksum, a.remote_checksum
+    );
+    println!(
+        "{b_path}: desync on frame {}, local checksum {}, remote checksum {}",
+        b.frame, b.local_checksum, b.remote_checksum
+    );
+
+    // walk through the frames both dumps know about, oldest first
+    let mut first_diff = None;
+    for state_a in &a.states {
+        let frame = state_a.frame;
+        let Some(state_b) = b.state(frame) else {
+            continue;
+        };
+        let mut diffs = diff_states(state_a, state_b);
+        // the inputs of a frame advance its state, so they explain differences in the next one
+        if let (Some(inputs_a), Some(inputs_b)) = (a.inputs(frame), b.inputs(frame)) {
+            diffs.extend(diff_inputs(inputs_a, inputs_b));
+        }
+        if diffs.is_empty() {
+            continue;
+        }
+        first_diff.get_or_insert(frame);
+        println!("frame {frame}:");
+        for diff in diffs {
+            println!("    {diff}");
+        }
+    }
+
+    match first_diff {
+        Some(frame) => {
+            println!("first difference on frame {frame}");
+            exit(1);
+        }
+        None => println!("no differences in the frames both dumps contain"),
+    }
+}
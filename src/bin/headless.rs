@@ -0,0 +1,372 @@
+//! Runs the simulation without a window and prints the checksum of every frame.
+//! Useful for determinism and regression runs on machines without a GPU.
+
+use std::io::{self, Write};
+use std::process::exit;
+
+use ggrs::{GgrsError, GgrsRequest, InputStatus, SessionBuilder};
+use ggrs_demo::bot::Bot;
+use ggrs_demo::map::Map;
+use ggrs_demo::sim::{GGRSConfig, Input, Rules, State, FPS};
+
+const HELP_STRING: &str = "usage: headless [OPTIONS]
+
+Simulates the demo without a window and prints `<frame> <checksum>` for every frame.
+
+options:
+    --frames N            number of frames to simulate (default: 600)
+    --players N           number of players, 1 to 4 (default: 2)
+    --seed N              seed for the random inputs and the game (default: 1)
+    --script PATH         read inputs from a file instead of generating random ones
+    --bots                let bots play for every player instead of generating random inputs
+    --map PATH            play on the map in the given RON file (default: empty arena)
+    --screen-wrap         ships leaving the arena reappear on the opposite edge
+    --check-distance N    run through a GGRS SyncTestSession with the given check distance
+    -h, --help            print this message
+
+script format:
+    one line per frame, with one input bitmask per player separated by whitespace.
+    empty lines and lines starting with '#' are ignored. frames after the end of the
+    script receive no input.";
+
+struct Args {
+    frames: usize,
+    players: usize,
+    seed: u32,
+    script: Option<String>,
+    bots: bool,
+    check_distance: Option<usize>,
+    map: Map,
+    rules: Rules,
+}
+
+fn die(msg: &str) -> ! {
+    eprintln!("Error: {msg}\n\n{HELP_STRING}");
+    exit(2);
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for {flag}"))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {flag}: {value}"))
+}
+
+// parses the arguments without the program name, returns `None` if the help was asked for
+fn parse_args(arguments: impl IntoIterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut args = Args {
+        frames: 600,
+        players: 2,
+        seed: 1,
+        script: None,
+        bots: false,
+        check_distance: None,
+        map: Map::default(),
+        rules: Rules::default(),
+    };
+
+    let mut iter = arguments.into_iter();
+    while let Some(flag) = iter.next() {
+        match flag.as_str() {
+            "--frames" => args.frames = parse_value(&flag, iter.next())?,
+            "--players" => args.players = parse_value(&flag, iter.next())?,
+            "--seed" => args.seed = parse_value(&flag, iter.next())?,
+            "--script" => args.script = Some(parse_value(&flag, iter.next())?),
+            "--bots" => args.bots = true,
+            "--map" => {
+                let path: String = parse_value(&flag, iter.next())?;
+                args.map = Map::load(path.as_ref())
+                    .map_err(|e| format!("map {path} could not be loaded: {e}"))?;
+            }
+            "--screen-wrap" => args.rules.screen_wrap = true,
+            "--check-distance" => args.check_distance = Some(parse_value(&flag, iter.next())?),
+            "-h" | "--help" => return Ok(None),
+            _ => return Err(format!("unknown argument: {flag}")),
+        }
+    }
+
+    if args.players == 0 || args.players > 4 {
+        return Err("the number of players must be between 1 and 4".to_owned());
+    }
+    if args.bots && args.script.is_some() {
+        return Err("--bots and --script cannot be combined".to_owned());
+    }
+    Ok(Some(args))
+}
+
+// one line per frame with one input bitmask per player, see the help for the details
+fn parse_script(content: &str, num_players: usize) -> Result<Vec<Vec<u8>>, String> {
+    let mut frames = Vec::new();
+    for (line_nr, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let inputs = line
+            .split_whitespace()
+            .map(|inp| {
+                inp.parse()
+                    .map_err(|_| format!("{}: invalid input {inp}", line_nr + 1))
+            })
+            .collect::<Result<Vec<u8>, _>>()?;
+        if inputs.len() != num_players {
+            return Err(format!(
+                "{}: expected {num_players} inputs, found {}",
+                line_nr + 1,
+                inputs.len()
+            ));
+        }
+        frames.push(inputs);
+    }
+    Ok(frames)
+}
+
+// produces the inputs for every player, frame by frame
+enum InputSource {
+    Random { rng: u32 },
+    Script { frames: Vec<Vec<u8>> },
+    Bots { bots: Vec<Bot> },
+}
+
+impl InputSource {
+    fn random(seed: u32) -> Self {
+        // xorshift does not work with a seed of zero
+        Self::Random { rng: seed.max(1) }
+    }
+
+    fn script(path: &str, num_players: usize) -> Self {
+        let content = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| die(&format!("could not read script {path}: {e}")));
+        let frames =
+            parse_script(&content, num_players).unwrap_or_else(|e| die(&format!("{path}:{e}")));
+        Self::Script { frames }
+    }
+
+    fn bots(num_players: usize) -> Self {
+        Self::Bots {
+            bots: (0..num_players).map(Bot::for_handle).collect(),
+        }
+    }
+
+    // bots react to the state the inputs are applied to
+    fn inputs(&mut self, frame: usize, state: &State) -> Vec<Input> {
+        let num_players = state.num_players;
+        match self {
+            InputSource::Random { rng } => (0..num_players)
+                .map(|_| {
+                    // xorshift32
+                    *rng ^= *rng << 13;
+                    *rng ^= *rng >> 17;
+                    *rng ^= *rng << 5;
+                    Input {
+                        inp: (*rng & 0b11_1111) as u8,
+                    }
+                })
+                .collect(),
+            InputSource::Script { frames } => match frames.get(frame) {
+                Some(inputs) => inputs.iter().map(|&inp| Input { inp }).collect(),
+                None => vec![Input { inp: 0 }; num_players],
+            },
+            InputSource::Bots { bots } => bots.iter().map(|bot| bot.input(state)).collect(),
+        }
+    }
+}
+
+// prints `<frame> <checksum>` to `out` for every frame
+fn run_plain(args: &Args, source: &mut InputSource, out: &mut impl Write) -> io::Result<()> {
+    let mut state = State::new(args.players, args.seed as u64, &args.map, args.rules);
+    for frame in 0..args.frames {
+        let inputs = source
+            .inputs(frame, &state)
+            .into_iter()
+            .map(|inp| (inp, InputStatus::Confirmed))
+            .collect();
+        state.advance(inputs);
+        writeln!(out, "{} {}", state.frame, state.checksum())?;
+    }
+    Ok(())
+}
+
+// like `run_plain`, but through a SyncTestSession, fails on the first checksum mismatch
+fn run_synctest(
+    args: &Args,
+    source: &mut InputSource,
+    check_distance: usize,
+    out: &mut impl Write,
+) -> Result<(), String> {
+    let mut sess = SessionBuilder::<GGRSConfig>::new()
+        .with_num_players(args.players)
+        .with_fps(FPS as usize)
+        .expect("Invalid FPS")
+        .with_check_distance(check_distance)
+        .start_synctest_session()
+        .map_err(|e| format!("session could not be created: {e}"))?;
+
+    let mut state = State::new(args.players, args.seed as u64, &args.map, args.rules);
+    for frame in 0..args.frames {
+        for (handle, inp) in source.inputs(frame, &state).into_iter().enumerate() {
+            sess.add_local_input(handle, inp)
+                .expect("Invalid player handle");
+        }
+
+        match sess.advance_frame() {
+            Ok(requests) => {
+                for request in requests {
+                    match request {
+                        GgrsRequest::SaveGameState { cell, frame } => {
+                            assert_eq!(state.frame, frame);
+                            let checksum = state.checksum() as u128;
+                            cell.save(frame, Some(state.clone()), Some(checksum));
+                        }
+                        GgrsRequest::LoadGameState { cell, .. } => {
+                            state = cell.load().expect("No data found.");
+                        }
+                        GgrsRequest::AdvanceFrame { inputs } => state.advance(inputs),
+                    }
+                }
+            }
+            Err(GgrsError::MismatchedChecksum {
+                current_frame,
+                mismatched_frames,
+            }) => {
+                return Err(format!(
+                    "Checksum mismatch on frame {current_frame}, mismatched frames: {mismatched_frames:?}"
+                ));
+            }
+            Err(e) => {
+                panic!("Unknown error happened during SyncTestSession::<_>::advance_frame(): {e}")
+            }
+        }
+        writeln!(out, "{} {}", state.frame, state.checksum()).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{HELP_STRING}");
+            exit(0);
+        }
+        Err(e) => die(&e),
+    };
+    let mut source = match &args.script {
+        Some(path) => InputSource::script(path, args.players),
+        None if args.bots => InputSource::bots(args.players),
+        None => InputSource::random(args.seed),
+    };
+
+    let mut out = io::stdout().lock();
+    match args.check_distance {
+        Some(check_distance) => {
+            if let Err(e) = run_synctest(&args, &mut source, check_distance, &mut out) {
+                eprintln!("{e}");
+                exit(1);
+            }
+        }
+        None => run_plain(&args, &mut source, &mut out).expect("Could not write to stdout."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(arguments: &[&str]) -> Result<Option<Args>, String> {
+        parse_args(arguments.iter().map(|arg| arg.to_string()))
+    }
+
+    fn script_args(frames: usize, players: usize) -> Args {
+        let frames = frames.to_string();
+        let players = players.to_string();
+        parse(&["--frames", &frames, "--players", &players])
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn arguments_are_parsed() {
+        let map = concat!(env!("CARGO_MANIFEST_DIR"), "/maps/pillars.ron");
+        let args = parse(&[
+            "--frames",
+            "30",
+            "--players",
+            "3",
+            "--seed",
+            "7",
+            "--bots",
+            "--screen-wrap",
+            "--check-distance",
+            "4",
+            "--map",
+            map,
+        ]);
+        let args = args.unwrap().unwrap();
+        assert_eq!(
+            (args.frames, args.players, args.seed, args.bots),
+            (30, 3, 7, true)
+        );
+        assert!(args.rules.screen_wrap);
+        assert_eq!(args.check_distance, Some(4));
+        assert_eq!(args.map.name, "Pillars");
+
+        let defaults = parse(&[]).unwrap().unwrap();
+        assert_eq!((defaults.frames, defaults.players), (600, 2));
+        assert!(defaults.script.is_none() && defaults.check_distance.is_none());
+        assert!(parse(&["--help"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        let invalid: [&[&str]; 7] = [
+            &["--frames"],
+            &["--frames", "many"],
+            &["--players", "0"],
+            &["--players", "5"],
+            &["--bots", "--script", "inputs.txt"],
+            &["--map", "does/not/exist.ron"],
+            &["--turbo"],
+        ];
+        for arguments in invalid {
+            assert!(parse(arguments).is_err(), "{arguments:?}");
+        }
+    }
+
+    #[test]
+    fn scripts_are_parsed() {
+        let script = "# up and fire\n1 16\n\n  0 0  \n# done\n8 4\n";
+        assert_eq!(
+            parse_script(script, 2),
+            Ok(vec![vec![1, 16], vec![0, 0], vec![8, 4]])
+        );
+        assert!(parse_script("1 2 3\n", 2)
+            .unwrap_err()
+            .contains("expected 2 inputs"));
+        assert!(parse_script("1\n256\n", 1)
+            .unwrap_err()
+            .starts_with("2: invalid input"));
+    }
+
+    #[test]
+    fn scripted_runs_print_a_checksum_per_frame() {
+        let args = script_args(40, 2);
+        let frames = parse_script("1 16\n2 8\n16 16\n", 2).unwrap();
+
+        let mut plain = Vec::new();
+        let mut source = InputSource::Script {
+            frames: frames.clone(),
+        };
+        run_plain(&args, &mut source, &mut plain).unwrap();
+        let plain = String::from_utf8(plain).unwrap();
+        assert_eq!(plain.lines().count(), 40);
+        assert!(plain.starts_with("1 "));
+        assert!(plain.lines().last().unwrap().starts_with("40 "));
+
+        // the same run through a SyncTestSession ends with the same checksums
+        let mut synctest = Vec::new();
+        let mut source = InputSource::Script { frames };
+        run_synctest(&args, &mut source, 2, &mut synctest).unwrap();
+        assert_eq!(String::from_utf8(synctest).unwrap(), plain);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::fixed::Fixed;
+use crate::sim::{
+    Input, Phase, State, ARENA_HEIGHT, ARENA_WIDTH, BULLET_SPEED, INPUT_DOWN, INPUT_FIRE,
+    INPUT_LEFT, INPUT_REMATCH, INPUT_RIGHT, INPUT_UP,
+};
+
+// orbiting bots try to keep this distance to their target
+const ORBIT_DISTANCE: Fixed = Fixed::from_int(200);
+// bots only shoot at targets this close
+const FIRE_RANGE: Fixed = Fixed::from_int(400);
+// the cosine of the largest angle between the nose and the wanted direction at which bots thrust
+const THRUST_ALIGNMENT: Fixed = Fixed::from_ratio(7, 10);
+// the cosine of the largest angle between the nose and the target at which bots shoot
+const AIM_ALIGNMENT: Fixed = Fixed::from_ratio(97, 100);
+// evading and orbiting bots turn away from arena borders closer than this, so they do not get cornered
+const WALL_MARGIN: Fixed = Fixed::from_int(100);
+// bots ignore the wanted direction while it is this close to straight ahead, so they do not wiggle
+const STEER_DEADZONE: Fixed = Fixed::from_ratio(5, 100);
+
+/// How a bot moves relative to the closest opponent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Behaviour {
+    // flies straight at the opponent
+    Chase,
+    // flies away from the opponent, shooting whenever it happens to face it
+    Evade,
+    // circles around the opponent
+    Orbit,
+}
+
+impl Behaviour {
+    pub const ALL: [Behaviour; 3] = [Behaviour::Chase, Behaviour::Evade, Behaviour::Orbit];
+}
+
+/// `Bot` produces the input of a player from the current `State`, instead of a keyboard.
+/// It keeps no state of its own, so the same state always gives the same input.
+#[derive(Copy, Clone, Debug)]
+pub struct Bot {
+    pub handle: usize,
+    pub behaviour: Behaviour,
+}
+
+impl Bot {
+    pub fn new(handle: usize, behaviour: Behaviour) -> Self {
+        Self { handle, behaviour }
+    }
+
+    /// picks the behaviour by handle, so several bots in a session act differently
+    pub fn for_handle(handle: usize) -> Self {
+        Self::new(handle, Behaviour::ALL[handle % Behaviour::ALL.len()])
+    }
+
+    pub fn input(&self, state: &State) -> Input {
+        // bots are always ready for a rematch
+        let mut inp = INPUT_REMATCH;
+        if state.phase != Phase::Playing || !state.is_alive(self.handle) {
+            return Input { inp };
+        }
+
+        let position = state.positions[self.handle];
+        let distance_to = |other: (Fixed, Fixed)| {
+            let (dx, dy) = state.offset(position, other);
+            Fixed::hypot(dx, dy)
+        };
+        let Some(target) = (0..state.num_players)
+            .filter(|&i| i != self.handle && state.is_alive(i))
+            .min_by_key(|&i| distance_to(state.positions[i]))
+        else {
+            return Input { inp };
+        };
+        let (dx, dy) = state.offset(position, state.positions[target]);
+        let Some((to_x, to_y, distance)) = direction((dx, dy)) else {
+            return Input { inp };
+        };
+        // aim where the target will be once a bullet gets there
+        let (vel_x, vel_y) = state.velocities[self.handle];
+        let (target_vel_x, target_vel_y) = state.velocities[target];
+        let flight_time = distance / BULLET_SPEED;
+        let lead = (
+            dx + (target_vel_x - vel_x) * flight_time,
+            dy + (target_vel_y - vel_y) * flight_time,
+        );
+        let (aim_x, aim_y, _) = direction(lead).unwrap_or((to_x, to_y, distance));
+
+        let (mut want_x, mut want_y) = match self.behaviour {
+            Behaviour::Chase => (to_x, to_y),
+            Behaviour::Evade => (-to_x, -to_y),
+            Behaviour::Orbit => {
+                // fly sideways, leaning in or out to hold the distance
+                let lean =
+                    ((distance - ORBIT_DISTANCE) / ORBIT_DISTANCE).clamp(-Fixed::ONE, Fixed::ONE);
+                (-to_y + to_x * lean, to_x + to_y * lean)
+            }
+        };
+        if self.behaviour != Behaviour::Chase && !state.rules.screen_wrap {
+            let away = |pos: Fixed, size: Fixed| {
+                if pos < WALL_MARGIN {
+                    Fixed::ONE
+                } else if pos > size - WALL_MARGIN {
+                    -Fixed::ONE
+                } else {
+                    Fixed::ZERO
+                }
+            };
+            want_x += away(position.0, ARENA_WIDTH);
+            want_y += away(position.1, ARENA_HEIGHT);
+        }
+        // a power-up closer than the target is worth a detour, unless running away
+        let power_up = state
+            .power_ups
+            .iter()
+            .map(|power_up| power_up.position)
+            .filter(|&other| distance_to(other) < distance)
+            .min_by_key(|&other| distance_to(other));
+        let wanted = match power_up {
+            Some(other) if self.behaviour != Behaviour::Evade => {
+                direction(state.offset(position, other))
+            }
+            _ => direction((want_x, want_y)),
+        };
+        let Some((want_x, want_y, _)) = wanted else {
+            return Input { inp };
+        };
+
+        // turn towards the wanted direction and thrust once roughly facing it
+        let rotation = state.rotations[self.handle];
+        let (nose_x, nose_y) = (rotation.cos(), rotation.sin());
+        let cross = nose_x * want_y - nose_y * want_x;
+        let dot = nose_x * want_x + nose_y * want_y;
+        if cross.abs() > STEER_DEADZONE || dot < Fixed::ZERO {
+            inp |= if cross >= Fixed::ZERO {
+                INPUT_RIGHT
+            } else {
+                INPUT_LEFT
+            };
+        }
+        if dot > THRUST_ALIGNMENT {
+            inp |= INPUT_UP;
+        } else if dot < Fixed::ZERO {
+            // slowing down tightens the turn, so bots do not circle each other forever
+            inp |= INPUT_DOWN;
+        }
+
+        // shoot whenever the target is in front of the nose
+        if distance < FIRE_RANGE && nose_x * aim_x + nose_y * aim_y > AIM_ALIGNMENT {
+            inp |= INPUT_FIRE;
+        }
+
+        Input { inp }
+    }
+}
+
+// the unit vector in the direction of `(x, y)` and its length, if it has one
+fn direction((x, y): (Fixed, Fixed)) -> Option<(Fixed, Fixed, Fixed)> {
+    let length = Fixed::hypot(x, y);
+    if length == Fixed::ZERO {
+        return None;
+    }
+    Some((x / length, y / length, length))
+}
@@ -0,0 +1,446 @@
+use std::path::PathBuf;
+#[cfg(not(target_arch = "wasm32"))]
+use std::process::exit;
+
+#[cfg(target_arch = "wasm32")]
+use macroquad::prelude::warn;
+
+use crate::handshake::Role;
+use crate::lobby::{
+    LobbySelection, MAX_CHECK_DISTANCE, MAX_PLAYERS, MIN_CHECK_DISTANCE, MIN_PLAYERS,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+const HELP_STRING: &str = "usage: ggrs_demo [OPTIONS]
+
+Starts the demo in the lobby, or directly in the given mode.
+
+options:
+    --matchbox URL            address of the matchbox signaling server (default: ws://127.0.0.1:3536)
+    --room CODE               join the lobby with the given code right away
+    --players N               number of players, 2 to 4 (default: 2)
+    --mode MODE               start right away in one of these modes:
+                                online    join --room, or get matched with a random person
+                                synctest  run a local SyncTest
+                                local     play locally on one keyboard
+                                replay    watch the most recent replay
+    --check-distance N        check distance of the SyncTest, 2 to 10 (default: 2)
+    --bots N                  number of bots in local games and SyncTests (default: 0)
+    --prediction-window N     frames GGRS may predict ahead, 1 to 60 (default: 12)
+    --input-delay N           frames local inputs are delayed, 0 to 30 (default: 2)
+    --replay PATH             watch the given replay file, e.g. an older one or one from another peer
+    --debug on|off            show frame status, checksums and connection stats in game (default: on)
+    -h, --help                print this message
+
+every option can also be given as an environment variable, e.g. GGRS_DEMO_INPUT_DELAY=3 for
+--input-delay 3. options on the command line take precedence over environment variables.
+in the browser, the options are read from the query string of the page instead, e.g.
+index.html?room=4242&players=3.";
+
+#[cfg(not(target_arch = "wasm32"))]
+const ENV_PREFIX: &str = "GGRS_DEMO_";
+// GGRS keeps the inputs of every player in a queue of 128 frames, which has to hold the delayed
+// inputs of both peers and the predicted frames, larger values make it panic mid-match
+const MAX_PREDICTION_WINDOW: usize = 60;
+const MAX_INPUT_DELAY: usize = 30;
+// the options taking a value, in the order they are listed in the help
+const OPTIONS: [&str; 10] = [
+    "matchbox",
+    "room",
+    "players",
+    "mode",
+    "check-distance",
+    "bots",
+    "prediction-window",
+    "input-delay",
+    "replay",
+    "debug",
+];
+
+// what to start without going through the lobby
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Online,
+    SyncTest,
+    Local,
+    Replay,
+}
+
+// Config holds the settings that used to be constants, read from the environment and the command line
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub matchbox_addr: String,
+    pub room: Option<String>,
+    pub num_players: usize,
+    pub mode: Option<Mode>,
+    pub check_distance: usize,
+    pub bots: usize,
+    pub max_prediction_window: usize,
+    pub input_delay: usize,
+    pub replay: Option<PathBuf>,
+    pub debug_overlay: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            matchbox_addr: "ws://127.0.0.1:3536".to_owned(),
+            room: None,
+            num_players: 2,
+            mode: None,
+            check_distance: 2,
+            bots: 0,
+            max_prediction_window: 12,
+            input_delay: 2,
+            replay: None,
+            debug_overlay: true,
+        }
+    }
+}
+
+impl Config {
+    // reads the environment variables first, then the command line, exits on invalid options
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_env_and_args() -> Self {
+        let mut config = Self::default();
+        for option in OPTIONS {
+            let var = format!("{ENV_PREFIX}{}", option.to_uppercase().replace('-', "_"));
+            if let Ok(value) = std::env::var(&var) {
+                config
+                    .set(option, &value)
+                    .unwrap_or_else(|e| die(&format!("{var}: {e}")));
+            }
+        }
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                println!("{HELP_STRING}");
+                exit(0);
+            }
+            let Some(option) = arg.strip_prefix("--").filter(|o| OPTIONS.contains(o)) else {
+                die(&format!("unknown argument: {arg}"));
+            };
+            let value = args
+                .next()
+                .unwrap_or_else(|| die(&format!("missing value for {arg}")));
+            config
+                .set(option, &value)
+                .unwrap_or_else(|e| die(&format!("{arg}: {e}")));
+        }
+
+        config.validate().unwrap_or_else(|e| die(&e));
+        config
+    }
+
+    // reads the query string of the page, e.g. ?room=4242&players=3, invalid options are skipped
+    // with a warning since there is no terminal to exit to in the browser
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_url() -> Self {
+        let mut config = Self::default();
+        let Some(params) = web_sys::window()
+            .and_then(|window| window.location().search().ok())
+            .and_then(|search| web_sys::UrlSearchParams::new_with_str(&search).ok())
+        else {
+            return config;
+        };
+
+        for option in OPTIONS {
+            // input_delay reads nicer in a link than input-delay, both are accepted
+            let Some(value) = params
+                .get(option)
+                .or_else(|| params.get(&option.replace('-', "_")))
+            else {
+                continue;
+            };
+            // an invalid option must not take the others with it, so each one is checked alone
+            let mut changed = config.clone();
+            match changed.set(option, &value).and_then(|_| changed.validate()) {
+                Ok(()) => config = changed,
+                Err(e) => warn!("Ignoring {} in the page address: {}", option, e),
+            }
+        }
+        config
+    }
+
+    // sets the option with the given name, without the leading dashes
+    pub fn set(&mut self, option: &str, value: &str) -> Result<(), String> {
+        match option {
+            "matchbox" => {
+                if !value.starts_with("ws://") && !value.starts_with("wss://") {
+                    return Err(format!("not a websocket address: {value}"));
+                }
+                self.matchbox_addr = value.trim_end_matches('/').to_owned();
+            }
+            "room" => {
+                if value.is_empty() || !value.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    return Err(format!(
+                        "room codes may only contain letters and digits: {value}"
+                    ));
+                }
+                self.room = Some(value.to_owned());
+            }
+            "players" => self.num_players = parse_number(value)?,
+            "mode" => {
+                self.mode = Some(match value {
+                    "online" => Mode::Online,
+                    "synctest" => Mode::SyncTest,
+                    "local" => Mode::Local,
+                    "replay" => Mode::Replay,
+                    _ => return Err(format!("unknown mode: {value}")),
+                })
+            }
+            "check-distance" => self.check_distance = parse_number(value)?,
+            "bots" => self.bots = parse_number(value)?,
+            "prediction-window" => self.max_prediction_window = parse_number(value)?,
+            "input-delay" => self.input_delay = parse_number(value)?,
+            "replay" => {
+                if value.is_empty() {
+                    return Err("the path of the replay is empty".to_owned());
+                }
+                self.replay = Some(PathBuf::from(value));
+            }
+            "debug" => {
+                self.debug_overlay = match value {
+                    "on" | "true" | "1" => true,
+                    "off" | "false" | "0" => false,
+                    _ => return Err(format!("expected on or off: {value}")),
+                }
+            }
+            _ => return Err(format!("unknown option: {option}")),
+        }
+        Ok(())
+    }
+
+    // checks the options used by the mode to start in, the ones only used by other modes are
+    // clamped instead, so they are still valid once another mode is picked in the lobby
+    pub fn validate(&mut self) -> Result<(), String> {
+        if !(MIN_PLAYERS..=MAX_PLAYERS).contains(&self.num_players) {
+            return Err(format!(
+                "the number of players must be between {MIN_PLAYERS} and {MAX_PLAYERS}"
+            ));
+        }
+        if !(1..=MAX_PREDICTION_WINDOW).contains(&self.max_prediction_window) {
+            return Err(format!(
+                "the prediction window must be between 1 and {MAX_PREDICTION_WINDOW}"
+            ));
+        }
+        if self.input_delay > MAX_INPUT_DELAY {
+            return Err(format!(
+                "the input delay must be at most {MAX_INPUT_DELAY} frames"
+            ));
+        }
+        if self.room.is_some() && !matches!(self.mode, None | Some(Mode::Online)) {
+            return Err("a room can only be joined in online mode".to_owned());
+        }
+        if self.replay.is_some()
+            && (self.room.is_some() || !matches!(self.mode, None | Some(Mode::Replay)))
+        {
+            return Err("a replay can only be watched in replay mode".to_owned());
+        }
+
+        // GGRS can only roll back as far as it may predict
+        let max_check_distance = MAX_CHECK_DISTANCE.min(self.max_prediction_window - 1);
+        if self.mode == Some(Mode::SyncTest) {
+            if !(MIN_CHECK_DISTANCE..=MAX_CHECK_DISTANCE).contains(&self.check_distance) {
+                return Err(format!(
+                    "the check distance must be between {MIN_CHECK_DISTANCE} and {MAX_CHECK_DISTANCE}"
+                ));
+            }
+            if self.check_distance > max_check_distance {
+                return Err(
+                    "the check distance must be smaller than the prediction window".to_owned(),
+                );
+            }
+        } else {
+            self.check_distance = self
+                .check_distance
+                .min(max_check_distance)
+                .max(MIN_CHECK_DISTANCE);
+        }
+
+        if matches!(self.mode, Some(Mode::SyncTest | Mode::Local)) {
+            if self.bots >= self.num_players {
+                return Err("at least one player has to be left for the keyboard".to_owned());
+            }
+        } else {
+            self.bots = self.bots.min(self.num_players - 1);
+        }
+        Ok(())
+    }
+
+    // the selection to start with instead of waiting in the lobby, a room alone means joining it
+    // and a replay alone means watching it
+    pub fn selection(&self) -> Option<LobbySelection> {
+        let mode = match (self.mode, &self.room, &self.replay) {
+            (Some(mode), ..) => mode,
+            (None, Some(_), _) => Mode::Online,
+            (None, None, Some(_)) => Mode::Replay,
+            (None, None, None) => return None,
+        };
+        Some(match mode {
+            Mode::Online => LobbySelection::Online {
+                room_id: match &self.room {
+                    Some(room) => format!("macro{room}"),
+                    None => format!("macro?next={}", self.num_players),
+                },
+                role: Role::Player,
+                num_players: self.num_players,
+            },
+            Mode::SyncTest => LobbySelection::SyncTest {
+                check_distance: self.check_distance,
+                num_players: self.num_players,
+                bots: self.bots,
+            },
+            Mode::Local => LobbySelection::Local {
+                num_players: self.num_players,
+                bots: self.bots,
+            },
+            Mode::Replay => LobbySelection::Replay {
+                path: self.replay.clone(),
+            },
+        })
+    }
+}
+
+fn parse_number(value: &str) -> Result<usize, String> {
+    value.parse().map_err(|_| format!("not a number: {value}"))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn die(msg: &str) -> ! {
+    eprintln!("Error: {msg}\n\n{HELP_STRING}");
+    exit(2);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn config(options: &[(&str, &str)]) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (option, value) in options {
+            config.set(option, value)?;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn unknown_options_are_rejected() {
+        assert!(Config::default().set("speed", "2").is_err());
+        // options are given without their leading dashes
+        assert!(Config::default().set("--players", "3").is_err());
+    }
+
+    #[test]
+    fn numbers_must_be_numbers() {
+        for option in ["players", "check-distance", "bots", "input-delay"] {
+            assert!(Config::default().set(option, "three").is_err(), "{option}");
+            assert!(Config::default().set(option, "-1").is_err(), "{option}");
+        }
+        assert_eq!(parse_number("12"), Ok(12));
+    }
+
+    #[test]
+    fn player_count_is_limited() {
+        assert!(config(&[("players", "1")]).is_err());
+        assert!(config(&[("players", "5")]).is_err());
+        for players in MIN_PLAYERS..=MAX_PLAYERS {
+            let config = config(&[("players", &players.to_string())]).unwrap();
+            assert_eq!(config.num_players, players);
+        }
+    }
+
+    #[test]
+    fn prediction_window_and_input_delay_are_limited() {
+        assert!(config(&[("prediction-window", "0")]).is_err());
+        assert!(config(&[("prediction-window", "61")]).is_err());
+        let window = config(&[("prediction-window", "60")]).unwrap();
+        assert_eq!(window.max_prediction_window, MAX_PREDICTION_WINDOW);
+
+        assert!(config(&[("input-delay", "31")]).is_err());
+        assert!(config(&[("input-delay", "200")]).is_err());
+        for delay in [0, MAX_INPUT_DELAY] {
+            let config = config(&[("input-delay", &delay.to_string())]).unwrap();
+            assert_eq!(config.input_delay, delay);
+        }
+    }
+
+    #[test]
+    fn debug_overlay_is_on_or_off() {
+        assert!(Config::default().debug_overlay);
+        for value in ["off", "false", "0"] {
+            assert!(
+                !config(&[("debug", value)]).unwrap().debug_overlay,
+                "{value}"
+            );
+        }
+        for value in ["on", "true", "1"] {
+            let config = config(&[("debug", "off"), ("debug", value)]).unwrap();
+            assert!(config.debug_overlay, "{value}");
+        }
+        assert!(Config::default().set("debug", "maybe").is_err());
+    }
+
+    #[test]
+    fn check_distance_is_validated_for_synctests_only() {
+        let synctest = ("mode", "synctest");
+        assert!(config(&[synctest, ("check-distance", "1")]).is_err());
+        assert!(config(&[synctest, ("check-distance", "11")]).is_err());
+        assert!(config(&[synctest, ("prediction-window", "2")]).is_err());
+        let checked = config(&[
+            synctest,
+            ("check-distance", "3"),
+            ("prediction-window", "4"),
+        ]);
+        assert_eq!(checked.unwrap().check_distance, 3);
+
+        // other modes never use it, so it is clamped instead
+        let online = config(&[("mode", "online"), ("prediction-window", "2")]).unwrap();
+        assert_eq!(online.check_distance, MIN_CHECK_DISTANCE);
+        let lobby = config(&[("check-distance", "50")]).unwrap();
+        assert_eq!(lobby.check_distance, MAX_CHECK_DISTANCE);
+        let lobby = config(&[("check-distance", "8"), ("prediction-window", "5")]).unwrap();
+        assert_eq!(lobby.check_distance, 4);
+    }
+
+    #[test]
+    fn bots_are_validated_for_local_games_only() {
+        assert!(config(&[("mode", "local"), ("bots", "2")]).is_err());
+        assert!(config(&[("mode", "synctest"), ("bots", "2")]).is_err());
+        let local = config(&[("mode", "local"), ("players", "3"), ("bots", "2")]).unwrap();
+        assert_eq!(local.bots, 2);
+
+        let online = config(&[("mode", "online"), ("bots", "5")]).unwrap();
+        assert_eq!(online.bots, online.num_players - 1);
+    }
+
+    #[test]
+    fn replays_are_watched_by_path() {
+        let watched = config(&[("replay", "replays/old.ggrs")]).unwrap();
+        assert!(matches!(
+            watched.selection(),
+            Some(LobbySelection::Replay { path: Some(path) }) if path == Path::new("replays/old.ggrs")
+        ));
+        assert!(matches!(
+            config(&[("mode", "replay")]).unwrap().selection(),
+            Some(LobbySelection::Replay { path: None })
+        ));
+        assert!(config(&[("replay", "old.ggrs"), ("mode", "local")]).is_err());
+        assert!(config(&[("replay", "old.ggrs"), ("room", "4242")]).is_err());
+    }
+
+    #[test]
+    fn rooms_are_joined_online() {
+        assert!(config(&[("room", "4242"), ("mode", "local")]).is_err());
+        assert!(Config::default().set("room", "42-42").is_err());
+        let config = config(&[("room", "4242"), ("players", "3")]).unwrap();
+        assert!(matches!(
+            config.selection(),
+            Some(LobbySelection::Online { room_id, num_players: 3, .. }) if room_id == "macro4242"
+        ));
+    }
+}
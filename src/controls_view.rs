@@ -0,0 +1,152 @@
+use macroquad::prelude::*;
+
+use crate::keymaps::{is_bindable, key_name, Action, Keymaps, MAX_LOCAL_PLAYERS};
+
+const COLUMN_WIDTH: f32 = 160.0;
+const ROW_HEIGHT: f32 = 30.0;
+
+// ControlsView lets every local player rebind their keys.
+// The arrow keys select a binding, ENTER waits for the new key, BACKSPACE restores the default keys.
+pub struct ControlsView {
+    keymaps: Keymaps,
+    player: usize,
+    action: usize,
+    // the next key pressed is bound to the selected action
+    waiting: bool,
+    error: Option<String>,
+}
+
+impl ControlsView {
+    pub fn new(keymaps: Keymaps) -> Self {
+        Self {
+            keymaps,
+            player: 0,
+            action: 0,
+            waiting: false,
+            error: None,
+        }
+    }
+
+    // returns the new bindings once the user leaves with ESC, they are only accepted without conflicts
+    pub fn run(&mut self) -> Option<Keymaps> {
+        let result = self.handle_keys();
+        self.render();
+        result
+    }
+
+    fn handle_keys(&mut self) -> Option<Keymaps> {
+        if self.waiting {
+            let key = get_last_key_pressed()?;
+            self.waiting = false;
+            if key == KeyCode::Escape {
+                self.error = None;
+            } else if is_bindable(key) {
+                self.error = None;
+                self.keymaps
+                    .bind(self.player, Action::ALL[self.action], key);
+            } else {
+                self.error = Some(format!(
+                    "{} is used by the menus and cannot be bound",
+                    key_name(key)
+                ));
+            }
+            return None;
+        }
+
+        if is_key_pressed(KeyCode::Up) {
+            self.player = (self.player + MAX_LOCAL_PLAYERS - 1) % MAX_LOCAL_PLAYERS;
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.player = (self.player + 1) % MAX_LOCAL_PLAYERS;
+        }
+        if is_key_pressed(KeyCode::Left) {
+            self.action = (self.action + Action::ALL.len() - 1) % Action::ALL.len();
+        }
+        if is_key_pressed(KeyCode::Right) {
+            self.action = (self.action + 1) % Action::ALL.len();
+        }
+        if is_key_pressed(KeyCode::Enter) {
+            self.waiting = true;
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.keymaps = Keymaps::default();
+            self.error = None;
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            match self.keymaps.conflicts().first() {
+                Some(conflict) => {
+                    self.error = Some(format!("Resolve the conflicts first: {conflict}"));
+                }
+                None => return Some(self.keymaps.clone()),
+            }
+        }
+        None
+    }
+
+    fn render(&self) {
+        clear_background(BLACK);
+        draw_text("Controls", 20.0, 40.0, 50.0, WHITE);
+        draw_text(
+            "- UP/DOWN/LEFT/RIGHT to select, ENTER to bind a new key, BACKSPACE to reset all",
+            20.0,
+            80.0,
+            30.0,
+            WHITE,
+        );
+        draw_text(
+            "- ESC to save and go back to the lobby",
+            20.0,
+            110.0,
+            30.0,
+            WHITE,
+        );
+
+        // one row for every player and one column for every action
+        let conflicts = self.keymaps.conflicts();
+        let table_y = 170.0;
+        for (i, action) in Action::ALL.iter().enumerate() {
+            let action_str = format!("{action:?}");
+            let x = 140.0 + i as f32 * COLUMN_WIDTH;
+            draw_text(&action_str, x, table_y, 30.0, GRAY);
+        }
+        for player in 0..MAX_LOCAL_PLAYERS {
+            let y = table_y + (player + 1) as f32 * ROW_HEIGHT;
+            let player_str = format!("Player {player}");
+            draw_text(&player_str, 20.0, y, 30.0, GRAY);
+            for (i, &action) in Action::ALL.iter().enumerate() {
+                let selected = player == self.player && i == self.action;
+                let conflicting = conflicts.iter().any(|conflict| {
+                    conflict.first == (player, action) || conflict.second == (player, action)
+                });
+                let key_str = if selected && self.waiting {
+                    "press a key...".to_owned()
+                } else {
+                    key_name(self.keymaps.key(player, action))
+                };
+                let color = if conflicting {
+                    RED
+                } else if selected {
+                    YELLOW
+                } else {
+                    WHITE
+                };
+                let x = 140.0 + i as f32 * COLUMN_WIDTH;
+                if selected {
+                    draw_rectangle_lines(x - 5.0, y - 22.0, COLUMN_WIDTH - 10.0, 28.0, 2.0, color);
+                }
+                draw_text(&key_str, x, y, 30.0, color);
+            }
+        }
+
+        let mut y = table_y + (MAX_LOCAL_PLAYERS + 2) as f32 * ROW_HEIGHT;
+        if let Some(error) = &self.error {
+            draw_text(error, 20.0, y, 30.0, RED);
+            y += ROW_HEIGHT;
+        }
+        for conflict in &conflicts {
+            let conflict_str = conflict.to_string();
+            draw_text(&conflict_str, 20.0, y, 30.0, RED);
+            y += ROW_HEIGHT;
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use ggrs::{Frame, InputStatus};
+use serde::{Deserialize, Serialize};
+
+use crate::replay::{unix_timestamp, ReplayFrame};
+use crate::sim::{Input, State};
+
+/// every desync dump starts with these bytes
+pub const DUMP_MAGIC: [u8; 4] = *b"GGDD";
+/// bump this whenever the file layout or the state changes in an incompatible way
+pub const DUMP_VERSION: u32 = 8;
+
+pub const DUMP_DIR: &str = "desyncs";
+
+/// how many saved frames before the desynced frame are written to a dump
+pub const DUMP_PRECEDING_FRAMES: Frame = 60;
+/// desyncs are only detected once a remote checksum arrives, so states are kept for a while.
+/// GGRS never rolls back this far, so older states can be forgotten
+const HISTORY_FRAMES: Frame = 600;
+
+/// A desync dump is laid out as `DUMP_MAGIC`, `DUMP_VERSION` and the `DesyncDump`, encoded with
+/// bincode. Both peers write one, and comparing them shows which field drifted first.
+#[derive(Serialize, Deserialize)]
+pub struct DesyncDump {
+    pub frame: Frame,
+    pub local_checksum: u128,
+    pub remote_checksum: u128,
+    /// the saved states up to and including the desynced frame, oldest first
+    pub states: Vec<State>,
+    /// the inputs each of the states, except the last, was advanced with
+    pub inputs: Vec<ReplayFrame>,
+}
+
+#[derive(Debug)]
+pub enum DumpError {
+    Io(io::Error),
+    NotADump,
+    UnsupportedVersion(u32),
+    Corrupt(bincode::Error),
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DumpError::Io(e) => write!(f, "could not read dump: {e}"),
+            DumpError::NotADump => write!(f, "not a desync dump"),
+            DumpError::UnsupportedVersion(version) => write!(
+                f,
+                "dump has version {version}, but only version {DUMP_VERSION} is supported"
+            ),
+            DumpError::Corrupt(e) => write!(f, "dump is corrupt: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for DumpError {}
+
+impl DesyncDump {
+    pub fn load(path: &Path) -> Result<Self, DumpError> {
+        let mut reader = BufReader::new(File::open(path).map_err(DumpError::Io)?);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic).map_err(DumpError::Io)?;
+        if magic != DUMP_MAGIC {
+            return Err(DumpError::NotADump);
+        }
+        let version: u32 = bincode::deserialize_from(&mut reader).map_err(DumpError::Corrupt)?;
+        if version != DUMP_VERSION {
+            return Err(DumpError::UnsupportedVersion(version));
+        }
+        bincode::deserialize_from(&mut reader).map_err(DumpError::Corrupt)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&DUMP_MAGIC)?;
+        bincode::serialize_into(&mut writer, &DUMP_VERSION).map_err(io::Error::other)?;
+        bincode::serialize_into(&mut writer, self).map_err(io::Error::other)?;
+        writer.flush()
+    }
+
+    pub fn state(&self, frame: Frame) -> Option<&State> {
+        self.states.iter().find(|state| state.frame == frame)
+    }
+
+    pub fn inputs(&self, frame: Frame) -> Option<&ReplayFrame> {
+        self.inputs.iter().find(|inputs| inputs.frame == frame)
+    }
+}
+
+/// returns a new, timestamped path inside `DUMP_DIR` for a desync on `frame`
+pub fn new_dump_path(frame: Frame) -> PathBuf {
+    let timestamp = unix_timestamp();
+    Path::new(DUMP_DIR).join(format!("desync-{timestamp}-frame{frame}.ggdd"))
+}
+
+/// `StateHistory` keeps the recently saved states and the inputs they were advanced with, so a
+/// dump can be written once a desync is detected. Resimulated frames overwrite older entries.
+#[derive(Default)]
+pub struct StateHistory {
+    states: BTreeMap<Frame, State>,
+    inputs: BTreeMap<Frame, ReplayFrame>,
+}
+
+impl StateHistory {
+    pub fn save(&mut self, state: &State) {
+        self.states.insert(state.frame, state.clone());
+
+        // forget everything that is too old to still be reported as desynced
+        let oldest = state.frame - HISTORY_FRAMES;
+        if self
+            .states
+            .first_key_value()
+            .is_some_and(|(frame, _)| *frame < oldest)
+        {
+            self.states = self.states.split_off(&oldest);
+            self.inputs = self.inputs.split_off(&oldest);
+        }
+    }
+
+    /// remembers the inputs `frame` was advanced with and the checksum of the resulting state
+    pub fn record(&mut self, frame: Frame, inputs: &[(Input, InputStatus)], checksum: u16) {
+        self.inputs
+            .insert(frame, ReplayFrame::new(frame, inputs, Some(checksum)));
+    }
+
+    /// collects the states and inputs leading up to `frame`, if it is still known
+    pub fn dump(
+        &self,
+        frame: Frame,
+        local_checksum: u128,
+        remote_checksum: u128,
+    ) -> Option<DesyncDump> {
+        if !self.states.contains_key(&frame) {
+            return None;
+        }
+        let first = frame - DUMP_PRECEDING_FRAMES;
+        Some(DesyncDump {
+            frame,
+            local_checksum,
+            remote_checksum,
+            states: self
+                .states
+                .range(first..=frame)
+                .map(|(_, s)| s.clone())
+                .collect(),
+            inputs: self
+                .inputs
+                .range(first..frame)
+                .map(|(_, i)| i.clone())
+                .collect(),
+        })
+    }
+}
+
+/// describes every field in which two states of the same frame differ
+pub fn diff_states(a: &State, b: &State) -> Vec<String> {
+    let mut diffs = Vec::new();
+    if a.frame != b.frame {
+        diffs.push(format!("frame: {} != {}", a.frame, b.frame));
+    }
+    if a.num_players != b.num_players {
+        diffs.push(format!(
+            "num_players: {} != {}",
+            a.num_players, b.num_players
+        ));
+        return diffs;
+    }
+    for i in 0..a.num_players {
+        if a.positions[i] != b.positions[i] {
+            diffs.push(format!(
+                "player {i} position: {:?} != {:?}",
+                a.positions[i], b.positions[i]
+            ));
+        }
+        if a.velocities[i] != b.velocities[i] {
+            diffs.push(format!(
+                "player {i} velocity: {:?} != {:?}",
+                a.velocities[i], b.velocities[i]
+            ));
+        }
+        if a.rotations[i] != b.rotations[i] {
+            diffs.push(format!(
+                "player {i} rotation: {:?} != {:?}",
+                a.rotations[i], b.rotations[i]
+            ));
+        }
+        let counters = [
+            ("health", a.health[i] as u32, b.health[i] as u32),
+            (
+                "respawn timer",
+                a.respawn_timers[i] as u32,
+                b.respawn_timers[i] as u32,
+            ),
+            ("cooldown", a.cooldowns[i] as u32, b.cooldowns[i] as u32),
+            ("kills", a.kills[i], b.kills[i]),
+            ("deaths", a.deaths[i], b.deaths[i]),
+            ("round kills", a.round_kills[i], b.round_kills[i]),
+            ("round wins", a.round_wins[i], b.round_wins[i]),
+            (
+                "rematch vote",
+                a.rematch_votes[i] as u32,
+                b.rematch_votes[i] as u32,
+            ),
+        ];
+        for (name, value_a, value_b) in counters {
+            if value_a != value_b {
+                diffs.push(format!("player {i} {name}: {value_a} != {value_b}"));
+            }
+        }
+        if a.active_power_ups[i] != b.active_power_ups[i] {
+            diffs.push(format!(
+                "player {i} active power-ups: {:?} != {:?}",
+                a.active_power_ups[i], b.active_power_ups[i]
+            ));
+        }
+    }
+    if a.phase != b.phase {
+        diffs.push(format!("phase: {:?} != {:?}", a.phase, b.phase));
+    }
+    if a.rng != b.rng {
+        diffs.push(format!("rng: {:?} != {:?}", a.rng, b.rng));
+    }
+    if a.power_ups != b.power_ups {
+        diffs.push(format!("power-ups: {:?} != {:?}", a.power_ups, b.power_ups));
+    }
+    if (a.power_up_timer, a.next_power_up_id) != (b.power_up_timer, b.next_power_up_id) {
+        diffs.push(format!(
+            "power-up timer and next id: {:?} != {:?}",
+            (a.power_up_timer, a.next_power_up_id),
+            (b.power_up_timer, b.next_power_up_id)
+        ));
+    }
+    if a.rules != b.rules {
+        diffs.push(format!("rules: {:?} != {:?}", a.rules, b.rules));
+    }
+    if a.obstacles != b.obstacles {
+        diffs.push(format!("obstacles: {:?} != {:?}", a.obstacles, b.obstacles));
+    }
+    for (slot, (bullet_a, bullet_b)) in a.bullets.iter().zip(b.bullets.iter()).enumerate() {
+        if bullet_a != bullet_b {
+            diffs.push(format!("bullet {slot}: {bullet_a:?} != {bullet_b:?}"));
+        }
+    }
+    diffs
+}
+
+/// describes every player whose input differs between two recorded frames
+pub fn diff_inputs(a: &ReplayFrame, b: &ReplayFrame) -> Vec<String> {
+    a.inputs
+        .iter()
+        .zip(b.inputs.iter())
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(i, (a, b))| format!("player {i} input: {a:?} != {b:?}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Map;
+    use crate::sim::{Rules, INPUT_LEFT};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ggrs_demo_{}_{name}.ggdd", std::process::id()))
+    }
+
+    #[test]
+    fn saved_dump_loads_back() {
+        let path = temp_path("round_trip");
+        let mut state = State::new(3, 7, &Map::default(), Rules::default());
+        let mut history = StateHistory::default();
+        for frame in 0..DUMP_PRECEDING_FRAMES + 10 {
+            history.save(&state);
+            let inputs = vec![(Input { inp: INPUT_LEFT }, InputStatus::Confirmed); 3];
+            state.advance(inputs.clone());
+            history.record(frame, &inputs, state.checksum());
+        }
+        let frame = state.frame - 1;
+        let dump = history.dump(frame, 1, 2).unwrap();
+        dump.save(&path).unwrap();
+
+        let loaded = DesyncDump::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.frame, frame);
+        assert_eq!(loaded.local_checksum, 1);
+        assert_eq!(loaded.remote_checksum, 2);
+        assert_eq!(loaded.states.len(), DUMP_PRECEDING_FRAMES as usize + 1);
+        assert_eq!(loaded.states.len(), dump.states.len());
+        for (a, b) in loaded.states.iter().zip(dump.states.iter()) {
+            assert!(diff_states(a, b).is_empty());
+        }
+        assert_eq!(loaded.inputs.len(), dump.inputs.len());
+        for (a, b) in loaded.inputs.iter().zip(dump.inputs.iter()) {
+            assert_eq!(a.frame, b.frame);
+            assert!(diff_inputs(a, b).is_empty());
+            assert_eq!(a.checksum, b.checksum);
+        }
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let path = temp_path("version");
+        let mut bytes = DUMP_MAGIC.to_vec();
+        bytes.extend(bincode::serialize(&(DUMP_VERSION + 1)).unwrap());
+        std::fs::write(&path, bytes).unwrap();
+
+        let result = DesyncDump::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            result,
+            Err(DumpError::UnsupportedVersion(version)) if version == DUMP_VERSION + 1
+        ));
+    }
+}
@@ -1,78 +1,100 @@
-use bytemuck::{Pod, Zeroable};
+use std::path::PathBuf;
+
 use ggrs::{
-    Config, Frame, GameStateCell, GgrsEvent, GgrsRequest, InputStatus, NetworkStats, P2PSession,
-    PlayerHandle, NULL_FRAME,
+    Frame, GameStateCell, GgrsEvent, GgrsRequest, InputStatus, NetworkStats, P2PSession,
+    PlayerHandle, SpectatorSession, NULL_FRAME,
+};
+use ggrs_demo::bot::Bot;
+use ggrs_demo::desync::{new_dump_path, StateHistory};
+use ggrs_demo::replay::{new_replay_path, ReplayRecorder};
+use ggrs_demo::settings::SessionSettings;
+use ggrs_demo::sim::{
+    GGRSConfig, Input, Phase, PowerUpKind, State, ARENA_HEIGHT, ARENA_WIDTH, CHECKSUM_PERIOD, FPS,
+    INPUT_DOWN, INPUT_FIRE, INPUT_LEFT, INPUT_REMATCH, INPUT_RIGHT, INPUT_UP, KILLS_PER_ROUND,
+    MAX_HEALTH, POWER_UP_RADIUS, ROUNDS_TO_WIN,
 };
 use macroquad::prelude::*;
-use matchbox_socket::PeerId;
-use serde::{Deserialize, Serialize};
 
-const FPS: u64 = 60;
-const CHECKSUM_PERIOD: i32 = 100;
+use crate::gamepad::Gamepads;
+use crate::keymaps::{Action, Keymaps};
+use crate::touch::TouchControls;
 
 const SHIP_HEIGHT: f32 = 50.;
 const SHIP_BASE: f32 = 40.;
-const ARENA_HEIGHT: f32 = 800.0;
-const ARENA_WIDTH: f32 = 800.0;
-
-const INPUT_UP: u8 = 0b0001;
-const INPUT_DOWN: u8 = 0b0010;
-const INPUT_LEFT: u8 = 0b0100;
-const INPUT_RIGHT: u8 = 0b1000;
-
-const MOVEMENT_SPEED: f32 = 15.0 / FPS as f32;
-const ROTATION_SPEED: f32 = 2.5 / FPS as f32;
-const MAX_SPEED: f32 = 7.0;
-const FRICTION: f32 = 0.98;
-
-#[repr(C)]
-#[derive(Copy, Clone, PartialEq, Pod, Zeroable)]
-pub struct Input {
-    pub inp: u8,
-}
-
-/// `GGRSConfig` holds all type parameters for GGRS Sessions
-#[derive(Debug)]
-pub struct GGRSConfig;
-impl Config for GGRSConfig {
-    type Input = Input;
-    type State = State;
-    type Address = PeerId;
+const HEALTH_BAR_WIDTH: f32 = 40.;
+const BULLET_RADIUS: f32 = 3.;
+// how long a power-up taken away by a rollback is pointed out
+const CORRECTION_FRAMES: Frame = 90;
+
+fn player_color(player: usize) -> Color {
+    match player {
+        0 => GOLD,
+        1 => BLUE,
+        2 => GREEN,
+        3 => RED,
+        _ => WHITE,
+    }
 }
 
-/// computes the fletcher16 checksum, copied from wikipedia: <https://en.wikipedia.org/wiki/Fletcher%27s_checksum>
-fn fletcher16(data: &[u8]) -> u16 {
-    let mut sum1: u16 = 0;
-    let mut sum2: u16 = 0;
-
-    for index in 0..data.len() {
-        sum1 = (sum1 + data[index] as u16) % 255;
-        sum2 = (sum2 + sum1) % 255;
+fn power_up_style(kind: PowerUpKind) -> (&'static str, Color) {
+    match kind {
+        PowerUpKind::Speed => ("Speed", LIME),
+        PowerUpKind::Shield => ("Shield", SKYBLUE),
+        PowerUpKind::RapidFire => ("Rapid fire", ORANGE),
     }
-
-    (sum2 << 8) | sum1
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Default)]
 // display the connection status for each remote player
 pub enum ConnectionStatus {
     Local,
+    #[default]
     Synchronizing,
     Running,
     Interrupted,
     Disconnected,
+    Replay,
 }
 
 pub enum FrameStatus {
     Normal,
     Slow,
     Halt,
+    // a spectator is following the host, advancing `catchup_speed` frames at once to catch up
+    Spectating {
+        frames_behind: usize,
+        catchup_speed: usize,
+    },
+    // a spectator fell so far behind that it cannot catch up anymore
+    SpectatorTooFarBehind,
+    // a SyncTestSession found differing checksums after resimulating frames
+    Mismatch {
+        current_frame: Frame,
+        mismatched_frames: Vec<Frame>,
+    },
+    // a replay produced a different checksum than the one recorded
+    Diverged {
+        frame: Frame,
+        recorded: u16,
+        simulated: u16,
+    },
 }
 
-impl Default for ConnectionStatus {
-    fn default() -> Self {
-        ConnectionStatus::Synchronizing
-    }
+// the first frame at which our checksum differed from the one of a remote peer
+pub struct Desync {
+    pub frame: Frame,
+    pub local_checksum: u128,
+    pub remote_checksum: u128,
+    pub handles: Vec<PlayerHandle>,
+    // where the states leading up to the desync were written to
+    pub dump_path: Option<PathBuf>,
+}
+
+// a power-up we predicted a player to collect, until a rollback showed otherwise
+struct Correction {
+    frame: Frame,
+    player: usize,
+    description: String,
 }
 
 #[derive(Default, Clone, Copy)]
@@ -94,18 +116,35 @@ pub struct Game {
     game_state: State,
     last_checksum: (Frame, u64),
     periodic_checksum: (Frame, u64),
+    recorder: Option<ReplayRecorder>,
+    history: StateHistory,
+    desync: Option<Desync>,
+    corrections: Vec<Correction>,
+    // players on this machine that are controlled by bots instead of the keyboard
+    bots: Vec<Bot>,
+    // lets bots play for every local player, so a peer can be left running unattended
+    autopilot: bool,
+    keymaps: Keymaps,
     pub connection_info: Vec<ConnectionInfo>,
     pub frame_info: FrameStatus,
 }
 
 impl Game {
-    pub fn new(num_players: usize) -> Self {
+    pub fn new(settings: &SessionSettings) -> Self {
+        let num_players = settings.num_players;
         assert!(num_players <= 4);
         Self {
             num_players,
-            game_state: State::new(num_players),
+            game_state: State::new(num_players, settings.seed, &settings.map, settings.rules),
             last_checksum: (NULL_FRAME, 0),
             periodic_checksum: (NULL_FRAME, 0),
+            recorder: None,
+            history: StateHistory::default(),
+            desync: None,
+            corrections: Vec::new(),
+            bots: Vec::new(),
+            autopilot: false,
+            keymaps: Keymaps::default(),
             connection_info: vec![ConnectionInfo::default(); num_players],
             frame_info: FrameStatus::Normal,
         }
@@ -117,15 +156,108 @@ impl Game {
         }
     }
 
+    // lets bots play for the last `count` players, so the keyboard players keep the first keymaps
+    pub fn add_bots(&mut self, count: usize) {
+        let first = self.num_players.saturating_sub(count);
+        self.bots = (first..self.num_players).map(Bot::for_handle).collect();
+    }
+
+    pub fn set_keymaps(&mut self, keymaps: Keymaps) {
+        self.keymaps = keymaps;
+    }
+
+    pub fn toggle_autopilot(&mut self) {
+        self.autopilot = !self.autopilot;
+        info!("Autopilot {}", if self.autopilot { "on" } else { "off" });
+    }
+
+    // records all confirmed inputs of this game to a new replay file
+    pub fn start_recording(&mut self, settings: &SessionSettings) {
+        let path = new_replay_path();
+        match ReplayRecorder::create(&path, settings, &self.game_state) {
+            Ok(recorder) => {
+                info!("Recording replay to {}", path.display());
+                self.recorder = Some(recorder);
+            }
+            Err(e) => warn!("Could not create replay file {}: {e}", path.display()),
+        }
+    }
+
+    // writes all inputs up to the confirmed frame to the replay file
+    pub fn confirm_frames(&mut self, confirmed_frame: Frame) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.confirm(confirmed_frame) {
+                warn!("Could not write replay, stopping the recording: {}", e);
+                self.recorder = None;
+            }
+        }
+    }
+
+    // shows the given state instead of one simulated through GGRS requests, used for replays
+    pub fn set_state(&mut self, state: &State) {
+        self.game_state = state.clone();
+        let checksum = self.game_state.checksum() as u64;
+        self.last_checksum = (self.game_state.frame, checksum);
+        if self.game_state.frame % CHECKSUM_PERIOD == 0 {
+            self.periodic_checksum = (self.game_state.frame, checksum);
+        }
+    }
+
     // for each request, call the appropriate function
     pub fn handle_requests(&mut self, requests: Vec<GgrsRequest<GGRSConfig>>) {
+        let predicted = self.game_state.clone();
+        let mut rolled_back = false;
         for request in requests {
             match request {
-                GgrsRequest::LoadGameState { cell, .. } => self.load_game_state(cell),
+                GgrsRequest::LoadGameState { cell, .. } => {
+                    rolled_back = true;
+                    self.load_game_state(cell)
+                }
                 GgrsRequest::SaveGameState { cell, frame } => self.save_game_state(cell, frame),
                 GgrsRequest::AdvanceFrame { inputs } => self.advance_frame(inputs),
             }
         }
+        if rolled_back {
+            self.find_corrections(&predicted);
+        }
+        let frame = self.game_state.frame;
+        self.corrections
+            .retain(|correction| frame - correction.frame < CORRECTION_FRAMES);
+    }
+
+    // finds power-ups the rendered prediction gave to a player, but the corrected state does not
+    fn find_corrections(&mut self, predicted: &State) {
+        for (player, power_ups) in predicted.active_power_ups.iter().enumerate() {
+            for power_up in power_ups {
+                let holder = self
+                    .game_state
+                    .active_power_ups
+                    .iter()
+                    .position(|active| active.iter().any(|active| active.id == power_up.id));
+                let lying = self
+                    .game_state
+                    .power_ups
+                    .iter()
+                    .any(|lying| lying.id == power_up.id);
+                let (name, _) = power_up_style(power_up.kind);
+                let description = match holder {
+                    Some(holder) if holder == player => continue,
+                    Some(holder) => format!("Player {holder} got the {name} first"),
+                    // it simply ran out or the ship was destroyed
+                    None if !lying => continue,
+                    None => format!("Missed the {name}"),
+                };
+                info!(
+                    "Rollback on frame {}: player {player}: {description}",
+                    self.game_state.frame
+                );
+                self.corrections.push(Correction {
+                    frame: self.game_state.frame,
+                    player,
+                    description,
+                });
+            }
+        }
     }
 
     pub fn handle_events(&mut self, sess: &mut P2PSession<GGRSConfig>) {
@@ -152,18 +284,88 @@ impl Game {
                     sess.handles_by_address(addr),
                     ConnectionStatus::Running,
                 ),
+                GgrsEvent::DesyncDetected {
+                    frame,
+                    local_checksum,
+                    remote_checksum,
+                    addr,
+                } => {
+                    let handles = sess.handles_by_address(addr);
+                    error!(
+                        "Desync on frame {} with player(s) {:?}: local checksum {}, remote checksum {}",
+                        frame, handles, local_checksum, remote_checksum
+                    );
+                    // later desyncs are most likely a consequence of the first one
+                    if self.desync.is_none() {
+                        let dump_path = self.dump_desync(frame, local_checksum, remote_checksum);
+                        self.desync = Some(Desync {
+                            frame,
+                            local_checksum,
+                            remote_checksum,
+                            handles,
+                            dump_path,
+                        });
+                    }
+                }
+                _ => (),
+            };
+        }
+    }
+
+    // writes the states and inputs leading up to the desynced frame to a file for later comparison
+    fn dump_desync(
+        &self,
+        frame: Frame,
+        local_checksum: u128,
+        remote_checksum: u128,
+    ) -> Option<PathBuf> {
+        let Some(dump) = self.history.dump(frame, local_checksum, remote_checksum) else {
+            warn!(
+                "Could not dump desync, frame {} is not in the history anymore",
+                frame
+            );
+            return None;
+        };
+        let path = new_dump_path(frame);
+        match dump.save(&path) {
+            Ok(()) => {
+                info!("Wrote desync dump to {}", path.display());
+                Some(path)
+            }
+            Err(e) => {
+                warn!("Could not write desync dump {}: {e}", path.display());
+                None
+            }
+        }
+    }
+
+    // a spectator is only connected to the host, who relays the inputs of all players
+    pub fn handle_spectator_events(&mut self, sess: &mut SpectatorSession<GGRSConfig>) {
+        let all_players: Vec<PlayerHandle> = (0..self.num_players).collect();
+        for event in sess.events() {
+            info!("Event: {:?}", event);
+            match event {
+                GgrsEvent::Synchronized { .. } | GgrsEvent::NetworkResumed { .. } => {
+                    self.set_connection_status(all_players.clone(), ConnectionStatus::Running)
+                }
+                GgrsEvent::Disconnected { .. } => {
+                    self.set_connection_status(all_players.clone(), ConnectionStatus::Disconnected)
+                }
+                GgrsEvent::NetworkInterrupted { .. } => {
+                    self.set_connection_status(all_players.clone(), ConnectionStatus::Interrupted)
+                }
                 _ => (),
             };
         }
     }
 
     // save current gamestate, create a checksum
-    // creating a checksum here is only relevant for SyncTestSessions
+    // SyncTestSessions compare the checksum after rolling back, P2PSessions send it to detect desyncs
     fn save_game_state(&mut self, cell: GameStateCell<State>, frame: Frame) {
         assert_eq!(self.game_state.frame, frame);
-        let buffer = bincode::serialize(&self.game_state).unwrap();
-        let checksum = fletcher16(&buffer) as u128;
+        let checksum = self.game_state.checksum() as u128;
         cell.save(frame, Some(self.game_state.clone()), Some(checksum));
+        self.history.save(&self.game_state);
     }
 
     // load gamestate and overwrite
@@ -172,16 +374,23 @@ impl Game {
     }
 
     fn advance_frame(&mut self, inputs: Vec<(Input, InputStatus)>) {
-        // advance the game state
-        self.game_state.advance(inputs);
+        let frame = self.game_state.frame;
+
+        // advance the game state, the inputs are kept for the replay and the desync history
+        self.game_state.advance(inputs.clone());
 
         // remember checksum to render it later
         // it is very inefficient to serialize the gamestate here just for the checksum
-        let buffer = bincode::serialize(&self.game_state).unwrap();
-        let checksum = fletcher16(&buffer) as u64;
-        self.last_checksum = (self.game_state.frame, checksum);
+        let checksum = self.game_state.checksum();
+        self.last_checksum = (self.game_state.frame, checksum as u64);
         if self.game_state.frame % CHECKSUM_PERIOD == 0 {
-            self.periodic_checksum = (self.game_state.frame, checksum);
+            self.periodic_checksum = (self.game_state.frame, checksum as u64);
+        }
+
+        // inputs are written to the replay once they are confirmed
+        self.history.record(frame, &inputs, checksum);
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(frame, &inputs, checksum);
         }
     }
 
@@ -190,23 +399,36 @@ impl Game {
         clear_background(BLACK);
 
         // center the game in the screen
-        let displ_x = (screen_width() - ARENA_WIDTH) / 2.0;
-        let displ_y = (screen_height() - ARENA_HEIGHT) / 2.0;
+        let (arena_width, arena_height) = (ARENA_WIDTH.to_f32(), ARENA_HEIGHT.to_f32());
+        let displ_x = (screen_width() - arena_width) / 2.0;
+        let displ_y = (screen_height() - arena_height) / 2.0;
         let displ_vec = Vec2::new(displ_x, displ_y);
 
-        draw_rectangle_lines(displ_x, displ_y, ARENA_WIDTH, ARENA_HEIGHT, 2.0, YELLOW);
+        draw_rectangle_lines(displ_x, displ_y, arena_width, arena_height, 2.0, YELLOW);
+        for obstacle in &self.game_state.obstacles {
+            let (x, y) = (obstacle.x as f32, obstacle.y as f32);
+            let (width, height) = (obstacle.width as f32, obstacle.height as f32);
+            draw_rectangle(displ_x + x, displ_y + y, width, height, GRAY);
+        }
 
-        // render players
+        // render the power-ups lying in the arena
+        for power_up in &self.game_state.power_ups {
+            let (x, y) = power_up.position;
+            let (x, y) = (displ_x + x.to_f32(), displ_y + y.to_f32());
+            let (name, color) = power_up_style(power_up.kind);
+            draw_circle(x, y, POWER_UP_RADIUS.to_f32(), color);
+            draw_text(&name[..1], x - 5., y + 6., 20., BLACK);
+        }
+
+        // render players, destroyed ships are hidden until they respawn
         for i in 0..self.num_players {
-            let color = match i {
-                0 => GOLD,
-                1 => BLUE,
-                2 => GREEN,
-                3 => RED,
-                _ => WHITE,
-            };
+            if !self.game_state.is_alive(i) {
+                continue;
+            }
+            let color = player_color(i);
             let (x, y) = self.game_state.positions[i];
-            let rotation = self.game_state.rotations[i] + std::f32::consts::PI / 2.0;
+            let (x, y) = (x.to_f32(), y.to_f32());
+            let rotation = self.game_state.rotations[i].to_radians() + std::f32::consts::PI / 2.0;
             let v1 = Vec2::new(
                 x + rotation.sin() * SHIP_HEIGHT / 2.,
                 y - rotation.cos() * SHIP_HEIGHT / 2.,
@@ -219,16 +441,217 @@ impl Game {
                 x + rotation.cos() * SHIP_BASE / 2. - rotation.sin() * SHIP_HEIGHT / 2.,
                 y + rotation.sin() * SHIP_BASE / 2. + rotation.cos() * SHIP_HEIGHT / 2.,
             );
-            draw_triangle(v1 + displ_vec, v2 + displ_vec, v3 + displ_vec, color);
+            // while crossing a wrapping edge, the ship is also drawn on the opposite side
+            let mut offsets = vec![Vec2::ZERO];
+            if self.game_state.rules.screen_wrap {
+                let reach = SHIP_HEIGHT / 2.;
+                let wrap = |pos: f32, size: f32| {
+                    if pos < reach {
+                        size
+                    } else if pos > size - reach {
+                        -size
+                    } else {
+                        0.
+                    }
+                };
+                let (wrap_x, wrap_y) = (wrap(x, arena_width), wrap(y, arena_height));
+                if wrap_x != 0. {
+                    offsets.push(Vec2::new(wrap_x, 0.));
+                }
+                if wrap_y != 0. {
+                    offsets.push(Vec2::new(0., wrap_y));
+                }
+                if wrap_x != 0. && wrap_y != 0. {
+                    offsets.push(Vec2::new(wrap_x, wrap_y));
+                }
+            }
+            for offset in offsets {
+                let offset = displ_vec + offset;
+                draw_triangle(v1 + offset, v2 + offset, v3 + offset, color);
+            }
+
+            // health bar below the ship
+            let health = self.game_state.health[i] as f32 / MAX_HEALTH as f32;
+            let bar_x = displ_x + x - HEALTH_BAR_WIDTH / 2.;
+            let bar_y = displ_y + y + SHIP_HEIGHT / 2. + 5.;
+            draw_rectangle(bar_x, bar_y, HEALTH_BAR_WIDTH, 4., DARKGRAY);
+            draw_rectangle(bar_x, bar_y, HEALTH_BAR_WIDTH * health, 4., color);
+
+            // collected power-ups, with the seconds they have left
+            for (j, power_up) in self.game_state.active_power_ups[i].iter().enumerate() {
+                let (name, power_up_color) = power_up_style(power_up.kind);
+                let seconds = (power_up.frames_left as u64).div_ceil(FPS);
+                let power_up_str = format!("{name} {seconds}");
+                draw_text(
+                    &power_up_str,
+                    bar_x,
+                    bar_y + 18. + j as f32 * 14.,
+                    16.,
+                    power_up_color,
+                );
+            }
+            if self.game_state.has_power_up(i, PowerUpKind::Shield) {
+                draw_circle_lines(displ_x + x, displ_y + y, SHIP_HEIGHT / 2. + 5., 2., SKYBLUE);
+            }
+
+            // point out power-ups a rollback took away from this ship
+            for (j, correction) in self
+                .corrections
+                .iter()
+                .filter(|correction| correction.player == i)
+                .enumerate()
+            {
+                let correction_str = format!("Rollback: {}", correction.description);
+                let correction_y = displ_y + y - SHIP_HEIGHT / 2. - 10. - j as f32 * 16.;
+                draw_text(&correction_str, bar_x, correction_y, 18., MAGENTA);
+            }
+        }
+
+        // render bullets in the color of their owner
+        for bullet in self
+            .game_state
+            .bullets
+            .iter()
+            .filter(|bullet| bullet.active)
+        {
+            let (x, y) = bullet.position;
+            let (x, y) = (displ_x + x.to_f32(), displ_y + y.to_f32());
+            draw_circle(x, y, BULLET_RADIUS, player_color(bullet.owner));
+        }
+
+        // render scoreboard
+        let score_x = screen_width() - 300.0;
+        let rules_str =
+            format!("{KILLS_PER_ROUND} kills win a round, {ROUNDS_TO_WIN} rounds the match");
+        draw_text("Scoreboard", score_x, 60.0, 30.0, WHITE);
+        draw_text(&rules_str, score_x, 80.0, 20.0, GRAY);
+        for i in 0..self.num_players {
+            let mut score_str = format!(
+                "Player {i}: {} rounds, {} kills, {} deaths",
+                self.game_state.round_wins[i], self.game_state.kills[i], self.game_state.deaths[i]
+            );
+            if !self.game_state.is_alive(i) {
+                score_str += " (respawning)";
+            }
+            draw_text(
+                &score_str,
+                score_x,
+                100.0 + i as f32 * 20.0,
+                25.0,
+                player_color(i),
+            );
         }
 
+        // render the phase of the match in the center of the arena
+        let phase_str = match self.game_state.phase {
+            Phase::Countdown { frames_left } => {
+                // count 3, 2, 1 instead of 2, 1, 0
+                format!("{}", (frames_left as u64).div_ceil(FPS))
+            }
+            Phase::Playing => String::new(),
+            Phase::RoundOver { winner, .. } => format!("Player {winner} wins the round!"),
+            Phase::MatchOver { winner } => format!("Player {winner} wins the match!"),
+        };
+        let center_x = displ_x + arena_width / 2.;
+        let center_y = displ_y + arena_height / 2.;
+        let size = measure_text(&phase_str, None, 60, 1.0);
+        draw_text(
+            &phase_str,
+            center_x - size.width / 2.,
+            center_y,
+            60.0,
+            WHITE,
+        );
+        if let Phase::MatchOver { .. } = self.game_state.phase {
+            let votes = self.game_state.rematch_votes.iter().filter(|v| **v).count();
+            let rematch_str = format!(
+                "Press ENTER for a rematch ({votes}/{} ready)",
+                self.num_players
+            );
+            let size = measure_text(&rematch_str, None, 30, 1.0);
+            draw_text(
+                &rematch_str,
+                center_x - size.width / 2.,
+                center_y + 40.,
+                30.0,
+                WHITE,
+            );
+        }
+
+        // render a banner that cannot be missed once the peers disagree on the game state
+        if let Some(desync) = &self.desync {
+            let banner_y = screen_height() - 60.0;
+            draw_rectangle(0.0, banner_y, screen_width(), 60.0, RED);
+            let desync_str = format!(
+                "DESYNC on frame {} with player(s) {:?}",
+                desync.frame, desync.handles
+            );
+            draw_text(&desync_str, 20.0, banner_y + 25.0, 30.0, WHITE);
+            let checksums_str = format!(
+                "local checksum {}, remote checksum {}",
+                desync.local_checksum, desync.remote_checksum
+            );
+            draw_text(&checksums_str, 20.0, banner_y + 48.0, 30.0, WHITE);
+            if let Some(path) = &desync.dump_path {
+                let dump_str = format!("dump: {}", path.display());
+                draw_text(
+                    &dump_str,
+                    screen_width() / 2.0,
+                    banner_y + 48.0,
+                    30.0,
+                    WHITE,
+                );
+            }
+        }
+    }
+
+    // renders frame status, checksums, connection stats and controls on top of the game
+    pub fn render_debug(&self) {
         // render frame status
-        let frame_status_str = match self.frame_info {
-            FrameStatus::Normal => "Status: Normal",
-            FrameStatus::Slow => "Status: Running Slow - Allows other players to catch up",
-            FrameStatus::Halt => "Status: Halting - Too far ahead of other players",
+        let (frame_status_str, frame_status_color) = match &self.frame_info {
+            FrameStatus::Normal => ("Status: Normal".to_owned(), WHITE),
+            FrameStatus::Slow => (
+                "Status: Running Slow - Allows other players to catch up".to_owned(),
+                WHITE,
+            ),
+            FrameStatus::Halt => (
+                "Status: Halting - Too far ahead of other players".to_owned(),
+                WHITE,
+            ),
+            FrameStatus::Spectating {
+                frames_behind,
+                catchup_speed,
+            } => (
+                format!(
+                    "Status: Spectating - {frames_behind} frame(s) behind host, catch-up speed {catchup_speed}x"
+                ),
+                WHITE,
+            ),
+            FrameStatus::SpectatorTooFarBehind => (
+                "Status: Spectator too far behind host to catch up".to_owned(),
+                RED,
+            ),
+            FrameStatus::Mismatch {
+                current_frame,
+                mismatched_frames,
+            } => (
+                format!(
+                    "Status: Checksum mismatch on frame {current_frame}, mismatched frames: {mismatched_frames:?}"
+                ),
+                RED,
+            ),
+            FrameStatus::Diverged {
+                frame,
+                recorded,
+                simulated,
+            } => (
+                format!(
+                    "Status: Replay diverged on frame {frame}, recorded checksum {recorded}, simulated {simulated}"
+                ),
+                RED,
+            ),
         };
-        draw_text(frame_status_str, 20.0, 20.0, 30.0, WHITE);
+        draw_text(&frame_status_str, 20.0, 20.0, 30.0, frame_status_color);
 
         // render checksums
         let last_checksum_str = format!(
@@ -248,6 +671,7 @@ impl Game {
             let mut info_str = format!("Player {i}: ");
             match con_info.status {
                 ConnectionStatus::Local => info_str += "local player",
+                ConnectionStatus::Replay => info_str += "replay",
                 ConnectionStatus::Synchronizing => {
                     info_str.push_str("Synchronizing, ");
                     info_str.push_str(&stats_to_string(con_info.stats));
@@ -270,145 +694,73 @@ impl Game {
 
         let y = 100.0 + self.num_players as f32 * 20.0;
         draw_text("---------------------------------", 20.0, y, 30.0, WHITE);
-        draw_text("Controls: W,A,S,D to move", 20.0, y + 20.0, 30.0, WHITE);
-    }
-
-    // creates a compact representation of currently pressed keys
-    pub fn local_input(&self, handle: PlayerHandle) -> Input {
-        let mut inp: u8 = 0;
 
-        // player 1 with WASD
-        if handle == 0 {
-            if is_key_down(KeyCode::W) {
-                inp |= INPUT_UP;
-            }
-            if is_key_down(KeyCode::A) {
-                inp |= INPUT_LEFT;
-            }
-            if is_key_down(KeyCode::S) {
-                inp |= INPUT_DOWN;
-            }
-            if is_key_down(KeyCode::D) {
-                inp |= INPUT_RIGHT;
-            }
-        }
-        // player 2 with arrow keys
-        if handle == 1 {
-            if is_key_down(KeyCode::Up) {
-                inp |= INPUT_UP;
-            }
-            if is_key_down(KeyCode::Left) {
-                inp |= INPUT_LEFT;
-            }
-            if is_key_down(KeyCode::Down) {
-                inp |= INPUT_DOWN;
-            }
-            if is_key_down(KeyCode::Right) {
-                inp |= INPUT_RIGHT;
-            }
+        // render controls, the n-th local player uses the n-th keymap
+        let local_handles = self
+            .connection_info
+            .iter()
+            .enumerate()
+            .filter(|(_, con_info)| matches!(con_info.status, ConnectionStatus::Local))
+            .map(|(handle, _)| handle);
+        for (local_player, handle) in local_handles.enumerate() {
+            let (move_keys, fire_key) = self.keymaps.describe(local_player);
+            let controls_str = match self.bots.iter().find(|bot| bot.handle == handle) {
+                Some(bot) => format!("Player {handle} is a bot ({:?})", bot.behaviour),
+                None if self.autopilot => {
+                    format!("Player {handle} is on autopilot (F2 to take over)")
+                }
+                None => {
+                    format!("Controls player {handle}: {move_keys} to move, {fire_key} to shoot")
+                }
+            };
+            let controls_y = y + 20.0 + local_player as f32 * 20.0;
+            draw_text(&controls_str, 20.0, controls_y, 30.0, WHITE);
         }
-
-        Input { inp }
     }
-}
-
-// BoxGameState holds all relevant information about the game state
-#[derive(Clone, Serialize, Deserialize)]
-pub struct State {
-    frame: i32,
-    num_players: usize,
-    positions: Vec<(f32, f32)>,
-    velocities: Vec<(f32, f32)>,
-    rotations: Vec<f32>,
-}
 
-impl State {
-    pub fn new(num_players: usize) -> Self {
-        let mut positions = Vec::new();
-        let mut velocities = Vec::new();
-        let mut rotations = Vec::new();
-
-        let r = ARENA_WIDTH as f32 / 4.0;
-
-        for i in 0..num_players as i32 {
-            let rot = i as f32 / num_players as f32 * 2.0 * std::f32::consts::PI;
-            let x = ARENA_WIDTH as f32 / 2.0 + r * rot.cos();
-            let y = ARENA_HEIGHT as f32 / 2.0 + r * rot.sin();
-            positions.push((x as f32, y as f32));
-            velocities.push((0.0, 0.0));
-            rotations.push((rot + std::f32::consts::PI) % (2.0 * std::f32::consts::PI));
-        }
-
-        Self {
-            frame: 0,
-            num_players,
-            positions,
-            velocities,
-            rotations,
+    // the input of a local player, from its bot if it has one and from the keyboard otherwise
+    pub fn input(
+        &self,
+        local_player: usize,
+        handle: PlayerHandle,
+        gamepads: &Gamepads,
+        touch: &TouchControls,
+    ) -> Input {
+        let bot = self.bots.iter().find(|bot| bot.handle == handle).copied();
+        match bot {
+            Some(bot) => bot.input(&self.game_state),
+            None if self.autopilot => Bot::for_handle(handle).input(&self.game_state),
+            None => self.local_input(local_player, gamepads, touch),
         }
     }
 
-    pub fn advance(&mut self, inputs: Vec<(Input, InputStatus)>) {
-        // increase the frame counter
-        self.frame += 1;
-
-        for i in 0..self.num_players {
-            // get input of that player
-            let input = match inputs[i].1 {
-                InputStatus::Confirmed => inputs[i].0.inp,
-                InputStatus::Predicted => inputs[i].0.inp,
-                InputStatus::Disconnected => INPUT_LEFT, // disconnected players spin
-            };
-
-            // old values
-            let (old_x, old_y) = self.positions[i];
-            let (old_vel_x, old_vel_y) = self.velocities[i];
-            let mut rot = self.rotations[i];
-
-            // slow down
-            let mut vel_x = old_vel_x * FRICTION;
-            let mut vel_y = old_vel_y * FRICTION;
-
-            // thrust
-            if input & INPUT_UP != 0 && input & INPUT_DOWN == 0 {
-                vel_x += MOVEMENT_SPEED * rot.cos();
-                vel_y += MOVEMENT_SPEED * rot.sin();
-            }
-            // break
-            if input & INPUT_UP == 0 && input & INPUT_DOWN != 0 {
-                vel_x -= MOVEMENT_SPEED * rot.cos();
-                vel_y -= MOVEMENT_SPEED * rot.sin();
-            }
-            // turn left
-            if input & INPUT_LEFT != 0 && input & INPUT_RIGHT == 0 {
-                rot = (rot - ROTATION_SPEED).rem_euclid(2.0 * std::f32::consts::PI);
-            }
-            // turn right
-            if input & INPUT_LEFT == 0 && input & INPUT_RIGHT != 0 {
-                rot = (rot + ROTATION_SPEED).rem_euclid(2.0 * std::f32::consts::PI);
+    // creates a compact representation of currently pressed keys, controller buttons and touches
+    // `local_player` counts the players on this machine, so each of them gets their own keys and controller
+    pub fn local_input(
+        &self,
+        local_player: usize,
+        gamepads: &Gamepads,
+        touch: &TouchControls,
+    ) -> Input {
+        let mut inp: u8 = gamepads.input(local_player).inp | touch.input(local_player).inp;
+
+        let actions = [
+            (Action::Up, INPUT_UP),
+            (Action::Left, INPUT_LEFT),
+            (Action::Down, INPUT_DOWN),
+            (Action::Right, INPUT_RIGHT),
+            (Action::Fire, INPUT_FIRE),
+        ];
+        for (action, bit) in actions {
+            if self.keymaps.is_down(local_player, action) {
+                inp |= bit;
             }
-
-            // limit speed
-            let magnitude = (vel_x * vel_x + vel_y * vel_y).sqrt();
-            if magnitude > MAX_SPEED {
-                vel_x = (vel_x * MAX_SPEED) / magnitude;
-                vel_y = (vel_y * MAX_SPEED) / magnitude;
-            }
-
-            // compute new position
-            let mut x = old_x + vel_x;
-            let mut y = old_y + vel_y;
-
-            // constrain players to canvas borders
-            x = x.max(0.0);
-            x = x.min(ARENA_WIDTH);
-            y = y.max(0.0);
-            y = y.min(ARENA_HEIGHT);
-
-            // update all state
-            self.positions[i] = (x, y);
-            self.velocities[i] = (vel_x, vel_y);
-            self.rotations[i] = rot;
         }
+        // everyone sharing the keyboard asks for the rematch together
+        if is_key_down(KeyCode::Enter) {
+            inp |= INPUT_REMATCH;
+        }
+
+        Input { inp }
     }
 }
@@ -0,0 +1,320 @@
+//! Deterministic fixed-point math for the simulation. Floating point results of `sin`, `cos` and
+//! `sqrt` may differ between platforms, integer arithmetic does not, so native and browser
+//! players compute the exact same states.
+
+use std::fmt;
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use serde::{Deserialize, Serialize};
+
+const FRAC_BITS: u32 = 16;
+
+/// A signed Q16.16 fixed-point number.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Fixed(pub i32);
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(1 << FRAC_BITS);
+
+    pub const fn from_int(value: i32) -> Self {
+        Fixed(value << FRAC_BITS)
+    }
+
+    /// `num / den`, rounded towards zero
+    pub const fn from_ratio(num: i32, den: i32) -> Self {
+        Fixed((((num as i64) << FRAC_BITS) / den as i64) as i32)
+    }
+
+    /// only meant for rendering, the simulation must never depend on floats
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / (1 << FRAC_BITS) as f32
+    }
+
+    /// exact for every fixed-point value, useful for printing
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / (1 << FRAC_BITS) as f64
+    }
+
+    pub fn abs(self) -> Self {
+        Fixed(self.0.abs())
+    }
+
+    /// the non-negative remainder of `self / rhs`, exact unlike a division
+    pub fn rem_euclid(self, rhs: Fixed) -> Self {
+        Fixed(self.0.rem_euclid(rhs.0))
+    }
+
+    /// the length of the vector `(x, y)`, rounded down, saturating at the largest `Fixed`
+    pub fn hypot(x: Fixed, y: Fixed) -> Fixed {
+        // both squares are at most 2^62, so their sum cannot overflow a u64
+        let (x, y) = (x.0.unsigned_abs() as u64, y.0.unsigned_abs() as u64);
+        Fixed(saturate((x * x + y * y).isqrt() as i64))
+    }
+}
+
+// clamps an intermediate result into the range of a Q16.16 number
+fn saturate(value: i64) -> i32 {
+    value.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+}
+
+impl fmt::Debug for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_f64())
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+
+    fn add(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, rhs: Fixed) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+
+    fn sub(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, rhs: Fixed) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+
+    fn neg(self) -> Fixed {
+        Fixed(-self.0)
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+
+    /// rounded towards negative infinity, saturating when the product does not fit
+    fn mul(self, rhs: Fixed) -> Fixed {
+        Fixed(saturate((self.0 as i64 * rhs.0 as i64) >> FRAC_BITS))
+    }
+}
+
+impl MulAssign for Fixed {
+    fn mul_assign(&mut self, rhs: Fixed) {
+        *self = *self * rhs;
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+
+    /// rounded towards zero, saturating when the quotient does not fit, e.g. for tiny divisors
+    fn div(self, rhs: Fixed) -> Fixed {
+        Fixed(saturate(((self.0 as i64) << FRAC_BITS) / rhs.0 as i64))
+    }
+}
+
+/// An angle in binary angle units, a full turn is 65536. Adding and subtracting wraps around,
+/// so angles always stay within a single turn.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Angle(pub u16);
+
+const QUARTER_TURN: u16 = 1 << 14;
+// the lookup table has 256 steps per quarter turn, the remaining bits are interpolated
+const LUT_SHIFT: u32 = 6;
+
+// sin of every 256th of a quarter turn, including both ends, as Q16.16
+#[rustfmt::skip]
+const SIN_LUT: [i32; 257] = [
+    0, 402, 804, 1206, 1608, 2010, 2412, 2814, 3216, 3617,
+    4019, 4420, 4821, 5222, 5623, 6023, 6424, 6824, 7224, 7623,
+    8022, 8421, 8820, 9218, 9616, 10014, 10411, 10808, 11204, 11600,
+    11996, 12391, 12785, 13180, 13573, 13966, 14359, 14751, 15143, 15534,
+    15924, 16314, 16703, 17091, 17479, 17867, 18253, 18639, 19024, 19409,
+    19792, 20175, 20557, 20939, 21320, 21699, 22078, 22457, 22834, 23210,
+    23586, 23961, 24335, 24708, 25080, 25451, 25821, 26190, 26558, 26925,
+    27291, 27656, 28020, 28383, 28745, 29106, 29466, 29824, 30182, 30538,
+    30893, 31248, 31600, 31952, 32303, 32652, 33000, 33347, 33692, 34037,
+    34380, 34721, 35062, 35401, 35738, 36075, 36410, 36744, 37076, 37407,
+    37736, 38064, 38391, 38716, 39040, 39362, 39683, 40002, 40320, 40636,
+    40951, 41264, 41576, 41886, 42194, 42501, 42806, 43110, 43412, 43713,
+    44011, 44308, 44604, 44898, 45190, 45480, 45769, 46056, 46341, 46624,
+    46906, 47186, 47464, 47741, 48015, 48288, 48559, 48828, 49095, 49361,
+    49624, 49886, 50146, 50404, 50660, 50914, 51166, 51417, 51665, 51911,
+    52156, 52398, 52639, 52878, 53114, 53349, 53581, 53812, 54040, 54267,
+    54491, 54714, 54934, 55152, 55368, 55582, 55794, 56004, 56212, 56418,
+    56621, 56823, 57022, 57219, 57414, 57607, 57798, 57986, 58172, 58356,
+    58538, 58718, 58896, 59071, 59244, 59415, 59583, 59750, 59914, 60075,
+    60235, 60392, 60547, 60700, 60851, 60999, 61145, 61288, 61429, 61568,
+    61705, 61839, 61971, 62101, 62228, 62353, 62476, 62596, 62714, 62830,
+    62943, 63054, 63162, 63268, 63372, 63473, 63572, 63668, 63763, 63854,
+    63944, 64031, 64115, 64197, 64277, 64354, 64429, 64501, 64571, 64639,
+    64704, 64766, 64827, 64884, 64940, 64993, 65043, 65091, 65137, 65180,
+    65220, 65259, 65294, 65328, 65358, 65387, 65413, 65436, 65457, 65476,
+    65492, 65505, 65516, 65525, 65531, 65535, 65536,
+];
+
+impl Angle {
+    pub const HALF_TURN: Angle = Angle(1 << 15);
+
+    /// `num / den` of a full turn
+    pub const fn from_turns(num: u32, den: u32) -> Self {
+        Angle(((num as u64 * (1 << 16)) / den as u64) as u16)
+    }
+
+    /// only meant for rendering, the simulation must never depend on floats
+    pub fn to_radians(self) -> f32 {
+        self.0 as f32 / 65536.0 * std::f32::consts::TAU
+    }
+
+    pub fn sin(self) -> Fixed {
+        let quadrant = self.0 / QUARTER_TURN;
+        let offset = self.0 % QUARTER_TURN;
+        match quadrant {
+            0 => quarter_sin(offset),
+            1 => quarter_sin(QUARTER_TURN - offset),
+            2 => -quarter_sin(offset),
+            _ => -quarter_sin(QUARTER_TURN - offset),
+        }
+    }
+
+    pub fn cos(self) -> Fixed {
+        (self + Angle(QUARTER_TURN)).sin()
+    }
+}
+
+// sin of an angle within the first quarter turn, `offset` may be a full quarter turn
+fn quarter_sin(offset: u16) -> Fixed {
+    let index = (offset >> LUT_SHIFT) as usize;
+    let frac = (offset & ((1 << LUT_SHIFT) - 1)) as i32;
+    if index + 1 >= SIN_LUT.len() {
+        return Fixed(SIN_LUT[SIN_LUT.len() - 1]);
+    }
+    let (low, high) = (SIN_LUT[index], SIN_LUT[index + 1]);
+    Fixed(low + (((high - low) * frac) >> LUT_SHIFT))
+}
+
+impl fmt::Debug for Angle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}°", self.0 as f64 / 65536.0 * 360.0)
+    }
+}
+
+impl Add for Angle {
+    type Output = Angle;
+
+    fn add(self, rhs: Angle) -> Angle {
+        Angle(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl Sub for Angle {
+    type Output = Angle;
+
+    fn sub(self, rhs: Angle) -> Angle {
+        Angle(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUARTER: Angle = Angle(QUARTER_TURN);
+
+    #[test]
+    fn sin_and_cos_of_right_angles() {
+        let right_angles = [
+            (Angle(0), Fixed::ZERO, Fixed::ONE),
+            (QUARTER, Fixed::ONE, Fixed::ZERO),
+            (Angle::HALF_TURN, Fixed::ZERO, -Fixed::ONE),
+            (Angle(3 * QUARTER_TURN), -Fixed::ONE, Fixed::ZERO),
+        ];
+        for (angle, sin, cos) in right_angles {
+            assert_eq!(angle.sin(), sin, "sin {angle:?}");
+            assert_eq!(angle.cos(), cos, "cos {angle:?}");
+        }
+    }
+
+    #[test]
+    fn sin_is_symmetric_in_every_quadrant() {
+        for a in 0..=u16::MAX {
+            let angle = Angle(a);
+            assert_eq!((Angle::HALF_TURN - angle).sin(), angle.sin(), "{angle:?}");
+            assert_eq!((angle + Angle::HALF_TURN).sin(), -angle.sin(), "{angle:?}");
+            assert_eq!((Angle(0) - angle).sin(), -angle.sin(), "{angle:?}");
+            assert_eq!(angle.cos(), (angle + QUARTER).sin(), "{angle:?}");
+        }
+    }
+
+    #[test]
+    fn sin_and_cos_are_close_to_floats() {
+        // the table is rounded to Q16.16 and linearly interpolated between its entries
+        let tolerance = 3.0 / 65536.0;
+        for a in 0..=u16::MAX {
+            let radians = a as f64 / 65536.0 * std::f64::consts::TAU;
+            let (sin, cos) = (Angle(a).sin().to_f64(), Angle(a).cos().to_f64());
+            assert!((sin - radians.sin()).abs() <= tolerance, "sin {a}: {sin}");
+            assert!((cos - radians.cos()).abs() <= tolerance, "cos {a}: {cos}");
+        }
+    }
+
+    #[test]
+    fn mul_saturates_large_products() {
+        let half = Fixed::from_ratio(1, 2);
+        assert_eq!(Fixed::from_int(3) * half, Fixed::from_ratio(3, 2));
+        assert_eq!(Fixed::from_int(-3) * half, Fixed::from_ratio(-3, 2));
+        // -1/65536 halved is rounded down, like the shift it is
+        assert_eq!(Fixed(-1) * half, Fixed(-1));
+        // 256 * 256 is just out of range, it saturates instead of wrapping around
+        let big = Fixed::from_int(256);
+        assert_eq!(big * big, Fixed(i32::MAX));
+        assert_eq!(-big * big, Fixed(i32::MIN));
+        assert_eq!(Fixed(i32::MIN) * Fixed(i32::MIN), Fixed(i32::MAX));
+    }
+
+    #[test]
+    fn div_rounds_towards_zero() {
+        let three = Fixed::from_int(3);
+        let two = Fixed::from_int(2);
+        assert_eq!(-three / two, Fixed::from_ratio(-3, 2));
+        assert_eq!(three / -two, Fixed::from_ratio(-3, 2));
+        assert_eq!(-three / -two, Fixed::from_ratio(3, 2));
+        // -1/3 is not exact, it is rounded up to the closer value to zero
+        assert_eq!(-Fixed::ONE / three, Fixed(-21845));
+    }
+
+    #[test]
+    fn div_by_small_divisors() {
+        let quarter = Fixed::from_ratio(1, 4);
+        assert_eq!(Fixed::from_int(3) / quarter, Fixed::from_int(12));
+        assert_eq!(Fixed::from_int(-3) / quarter, Fixed::from_int(-12));
+        assert_eq!(Fixed(1) / Fixed(1), Fixed::ONE);
+        // quotients that do not fit into Q16.16 saturate instead of wrapping around
+        assert_eq!(Fixed::ONE / Fixed(1), Fixed(i32::MAX));
+        assert_eq!(-Fixed::ONE / Fixed(1), Fixed(i32::MIN));
+    }
+
+    #[test]
+    fn hypot_of_large_components() {
+        let (x, y) = (Fixed::from_int(18000), Fixed::from_int(24000));
+        assert_eq!(Fixed::hypot(x, y), Fixed::from_int(30000));
+        assert_eq!(Fixed::hypot(-x, y), Fixed::from_int(30000));
+        assert_eq!(Fixed::hypot(Fixed::ZERO, Fixed(i32::MIN)), Fixed(i32::MAX));
+        assert_eq!(
+            Fixed::hypot(Fixed(i32::MIN), Fixed(i32::MIN)),
+            Fixed(i32::MAX)
+        );
+        assert_eq!(Fixed::hypot(Fixed(i32::MAX), Fixed::ZERO), Fixed(i32::MAX));
+    }
+}
@@ -0,0 +1,171 @@
+pub use imp::Gamepads;
+
+// controllers are only read with the `gamepad` feature, which needs libudev on Linux
+#[cfg(feature = "gamepad")]
+mod imp {
+    use ggrs_demo::sim::{
+        Input, INPUT_DOWN, INPUT_FIRE, INPUT_LEFT, INPUT_REMATCH, INPUT_RIGHT, INPUT_UP,
+    };
+    use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+    use macroquad::prelude::*;
+
+    // one controller for each local player, like the keymaps
+    const MAX_LOCAL_PLAYERS: usize = 4;
+    // stick deflections below this are ignored, so a stick resting slightly off center does not steer
+    const STICK_DEADZONE: f32 = 0.3;
+
+    // Gamepads assigns the connected controllers to the local players, in the order they were connected.
+    // Pressing START on a controller in the lobby hands it over to the next player.
+    pub struct Gamepads {
+        // `None` if the platform does not allow reading controllers
+        gilrs: Option<Gilrs>,
+        // the controller of each local player
+        players: [Option<GamepadId>; MAX_LOCAL_PLAYERS],
+        // controllers on which START was pressed since the last update
+        start_pressed: Vec<GamepadId>,
+    }
+
+    impl Gamepads {
+        pub fn new() -> Self {
+            let gilrs = match Gilrs::new() {
+                Ok(gilrs) => Some(gilrs),
+                Err(e) => {
+                    warn!("Controllers are not available: {}", e);
+                    None
+                }
+            };
+            let mut gamepads = Self {
+                gilrs,
+                players: [None; MAX_LOCAL_PLAYERS],
+                start_pressed: Vec::new(),
+            };
+            let connected: Vec<GamepadId> = gamepads
+                .gilrs
+                .iter()
+                .flat_map(|gilrs| gilrs.gamepads().map(|(id, _)| id))
+                .collect();
+            for id in connected {
+                gamepads.assign(id);
+            }
+            gamepads
+        }
+
+        // handles controllers being plugged in and out, has to be called every frame
+        pub fn update(&mut self) {
+            self.start_pressed.clear();
+            let Some(gilrs) = &mut self.gilrs else {
+                return;
+            };
+            let mut events = Vec::new();
+            while let Some(event) = gilrs.next_event() {
+                events.push(event);
+            }
+            for event in events {
+                match event.event {
+                    EventType::Connected => self.assign(event.id),
+                    EventType::Disconnected => {
+                        for player in self.players.iter_mut() {
+                            if *player == Some(event.id) {
+                                info!("Controller of local player unplugged");
+                                *player = None;
+                            }
+                        }
+                    }
+                    EventType::ButtonPressed(Button::Start, _) => self.start_pressed.push(event.id),
+                    _ => (),
+                }
+            }
+        }
+
+        // gives the controllers on which START was pressed to the next local player,
+        // swapping with the controller that player had
+        pub fn hand_over_pressed(&mut self) {
+            for id in std::mem::take(&mut self.start_pressed) {
+                let Some(from) = self.players.iter().position(|player| *player == Some(id)) else {
+                    continue;
+                };
+                let to = (from + 1) % MAX_LOCAL_PLAYERS;
+                self.players.swap(from, to);
+            }
+        }
+
+        // the name of the controller of a local player, if it has one
+        pub fn name(&self, local_player: usize) -> Option<String> {
+            let gilrs = self.gilrs.as_ref()?;
+            let id = (*self.players.get(local_player)?)?;
+            Some(gilrs.gamepad(id).name().to_owned())
+        }
+
+        // the buttons pressed on the controller of a local player
+        pub fn input(&self, local_player: usize) -> Input {
+            let mut inp: u8 = 0;
+            let (Some(gilrs), Some(Some(id))) = (&self.gilrs, self.players.get(local_player))
+            else {
+                return Input { inp };
+            };
+            let gamepad = gilrs.gamepad(*id);
+
+            let (stick_x, stick_y) = (
+                gamepad.value(Axis::LeftStickX),
+                gamepad.value(Axis::LeftStickY),
+            );
+            if gamepad.is_pressed(Button::DPadUp) || stick_y > STICK_DEADZONE {
+                inp |= INPUT_UP;
+            }
+            if gamepad.is_pressed(Button::DPadLeft) || stick_x < -STICK_DEADZONE {
+                inp |= INPUT_LEFT;
+            }
+            if gamepad.is_pressed(Button::DPadDown) || stick_y < -STICK_DEADZONE {
+                inp |= INPUT_DOWN;
+            }
+            if gamepad.is_pressed(Button::DPadRight) || stick_x > STICK_DEADZONE {
+                inp |= INPUT_RIGHT;
+            }
+            if gamepad.is_pressed(Button::South) || gamepad.is_pressed(Button::RightTrigger2) {
+                inp |= INPUT_FIRE;
+            }
+            if gamepad.is_pressed(Button::Start) {
+                inp |= INPUT_REMATCH;
+            }
+
+            Input { inp }
+        }
+
+        // gives a newly connected controller to the first local player without one
+        fn assign(&mut self, id: GamepadId) {
+            if self.players.contains(&Some(id)) {
+                return;
+            }
+            if let Some(local_player) = self.players.iter().position(Option::is_none) {
+                info!("Controller connected for local player {}", local_player);
+                self.players[local_player] = Some(id);
+            }
+        }
+    }
+}
+
+// without gilrs, there are never any controllers
+#[cfg(not(feature = "gamepad"))]
+mod imp {
+    use ggrs_demo::sim::Input;
+
+    pub struct Gamepads;
+
+    impl Gamepads {
+        pub fn new() -> Self {
+            Self
+        }
+
+        pub fn update(&mut self) {}
+
+        pub fn hand_over_pressed(&mut self) {}
+
+        pub fn name(&self, _local_player: usize) -> Option<String> {
+            None
+        }
+
+        pub fn input(&self, _local_player: usize) -> Input {
+            Input { inp: 0 }
+        }
+    }
+}
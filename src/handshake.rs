@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use ggrs_demo::rng::seed_from;
+use ggrs_demo::sim::Rules;
+use macroquad::prelude::*;
+use matchbox_socket::{MultipleChannels, PeerId, PeerState, WebRtcSocket};
+use serde::{Deserialize, Serialize};
+
+// the GGRS session runs on the first channel, the handshake on the second
+pub const GGRS_CHANNEL: usize = 0;
+pub const HANDSHAKE_CHANNEL: usize = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Role {
+    Player,
+    Spectator,
+}
+
+// what every peer announces about itself when connecting
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PeerInfo {
+    pub role: Role,
+    // the number of players this peer wants to play with, spectators adopt it from the players
+    pub num_players: usize,
+    // every peer, spectators included, has to simulate the same map
+    pub map_name: String,
+    pub map_hash: u64,
+    pub rules: Rules,
+}
+
+#[derive(Serialize, Deserialize)]
+enum HandshakeMessage {
+    Hello(PeerInfo),
+    // sent to peers that connect once the match has started, with the reason
+    Rejected(String),
+}
+
+// Before a session can start, every peer announces whether it joins as a player or a spectator.
+// Matchbox treats all peers equally, so this is sent over a reliable channel of the socket.
+pub struct Handshake {
+    local: PeerInfo,
+    // announcements of connected remote peers
+    peers: HashMap<PeerId, PeerInfo>,
+    // why a peer that already started the match turned us away
+    rejection: Option<String>,
+}
+
+impl Handshake {
+    pub fn new(local: PeerInfo) -> Self {
+        Self {
+            local,
+            peers: HashMap::new(),
+            rejection: None,
+        }
+    }
+
+    pub fn role(&self) -> Role {
+        self.local.role
+    }
+
+    // the number of players of the session, spectators follow whatever the players chose
+    pub fn num_players(&self) -> usize {
+        match self.local.role {
+            Role::Player => self.local.num_players,
+            Role::Spectator => self
+                .players(None)
+                .first()
+                .map_or(self.local.num_players, |peer| self.peers[peer].num_players),
+        }
+    }
+
+    // players that want to play with a different number of players than we do
+    pub fn mismatched_players(&self) -> Vec<(PeerId, usize)> {
+        let num_players = self.num_players();
+        self.peers
+            .iter()
+            .filter(|(_, info)| info.role == Role::Player && info.num_players != num_players)
+            .map(|(peer, info)| (*peer, info.num_players))
+            .collect()
+    }
+
+    // peers that selected a different map than we did, with the name of their map
+    pub fn mismatched_maps(&self) -> Vec<(PeerId, String)> {
+        self.peers
+            .iter()
+            .filter(|(_, info)| info.map_hash != self.local.map_hash)
+            .map(|(peer, info)| (*peer, info.map_name.clone()))
+            .collect()
+    }
+
+    // peers that chose different rules than we did
+    pub fn mismatched_rules(&self) -> Vec<(PeerId, Rules)> {
+        self.peers
+            .iter()
+            .filter(|(_, info)| info.rules != self.local.rules)
+            .map(|(peer, info)| (*peer, info.rules))
+            .collect()
+    }
+
+    // greets newly connected peers and receives their announcements
+    pub fn update(&mut self, socket: &mut WebRtcSocket<MultipleChannels>) {
+        for (peer, state) in socket.update_peers() {
+            match state {
+                PeerState::Connected => {
+                    let hello = HandshakeMessage::Hello(self.local.clone());
+                    let packet = bincode::serialize(&hello).unwrap().into_boxed_slice();
+                    socket.channel_mut(HANDSHAKE_CHANNEL).send(packet, peer);
+                }
+                PeerState::Disconnected => {
+                    self.peers.remove(&peer);
+                }
+            }
+        }
+
+        for (peer, packet) in socket.channel_mut(HANDSHAKE_CHANNEL).receive() {
+            match bincode::deserialize(&packet) {
+                Ok(HandshakeMessage::Hello(info)) => {
+                    info!("Peer {} joined: {:?}", peer, info);
+                    self.peers.insert(peer, info);
+                }
+                Ok(HandshakeMessage::Rejected(reason)) => {
+                    warn!("Peer {} rejected us: {}", peer, reason);
+                    self.rejection = Some(reason);
+                }
+                Err(e) => warn!("Invalid handshake message from {}: {}", peer, e),
+            }
+        }
+    }
+
+    // keeps answering during the match, the session cannot take in anyone who was not part of
+    // the handshake, so late players and spectators are told to leave instead of waiting forever
+    pub fn update_in_match(&mut self, socket: &mut WebRtcSocket<MultipleChannels>) {
+        let mut late_peers = Vec::new();
+        for (peer, state) in socket.update_peers() {
+            if state == PeerState::Connected {
+                late_peers.push(peer);
+            }
+        }
+
+        for (peer, packet) in socket.channel_mut(HANDSHAKE_CHANNEL).receive() {
+            match bincode::deserialize(&packet) {
+                // a hello that arrives after the session started comes too late to be added
+                Ok(HandshakeMessage::Hello(_)) if !self.peers.contains_key(&peer) => {
+                    late_peers.push(peer);
+                }
+                Ok(HandshakeMessage::Hello(_)) => (),
+                Ok(HandshakeMessage::Rejected(reason)) => {
+                    warn!("Peer {} rejected us: {}", peer, reason);
+                    self.rejection = Some(reason);
+                }
+                Err(e) => warn!("Invalid handshake message from {}: {}", peer, e),
+            }
+        }
+
+        for peer in late_peers {
+            info!("Turning away peer {}, the match has already started", peer);
+            let rejected = HandshakeMessage::Rejected(
+                "The match in this room has already started.".to_owned(),
+            );
+            let packet = bincode::serialize(&rejected).unwrap().into_boxed_slice();
+            socket.channel_mut(HANDSHAKE_CHANNEL).send(packet, peer);
+        }
+    }
+
+    // the reason a peer gave for turning us away, if one did
+    pub fn rejection(&self) -> Option<&str> {
+        self.rejection.as_deref()
+    }
+
+    // true once every connected peer has told us its role
+    pub fn is_complete(&self, socket: &WebRtcSocket<MultipleChannels>) -> bool {
+        socket
+            .connected_peers()
+            .all(|peer| self.peers.contains_key(&peer))
+    }
+
+    // ids of all players in the order of their player handles, including ourselves if we play
+    pub fn players(&self, our_id: Option<PeerId>) -> Vec<PeerId> {
+        let mut players: Vec<PeerId> = self.peers_with_role(Role::Player);
+        if let (Role::Player, Some(our_id)) = (self.local.role, our_id) {
+            players.push(our_id);
+        }
+        players.sort();
+        players
+    }
+
+    // ids of all remote spectators, sorted
+    pub fn spectators(&self) -> Vec<PeerId> {
+        let mut spectators = self.peers_with_role(Role::Spectator);
+        spectators.sort();
+        spectators
+    }
+
+    fn peers_with_role(&self, role: Role) -> Vec<PeerId> {
+        self.peers
+            .iter()
+            .filter(|(_, info)| info.role == role)
+            .map(|(peer, _)| *peer)
+            .collect()
+    }
+}
+
+// every peer knows the ids of all players, so they agree on the seed without sending it
+pub fn session_seed(players: &[PeerId]) -> u64 {
+    let ids: Vec<&[u8]> = players
+        .iter()
+        .map(|peer| peer.0.as_bytes().as_slice())
+        .collect();
+    seed_from(&ids)
+}
@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// up to this many players can share the keyboard
+pub const MAX_LOCAL_PLAYERS: usize = 4;
+// where the bindings are stored, as a file on native and as a localStorage item in the browser
+#[cfg(not(target_arch = "wasm32"))]
+const KEYMAPS_PATH: &str = "controls.ron";
+#[cfg(target_arch = "wasm32")]
+const KEYMAPS_STORAGE_KEY: &str = "ggrs_demo.controls";
+
+// the keys that can be bound, all others are used by menus or debug panels
+const BINDABLE_KEYS: [KeyCode; 76] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Kp0,
+    KeyCode::Kp1,
+    KeyCode::Kp2,
+    KeyCode::Kp3,
+    KeyCode::Kp4,
+    KeyCode::Kp5,
+    KeyCode::Kp6,
+    KeyCode::Kp7,
+    KeyCode::Kp8,
+    KeyCode::Kp9,
+    KeyCode::KpDecimal,
+    KeyCode::KpDivide,
+    KeyCode::KpMultiply,
+    KeyCode::KpSubtract,
+    KeyCode::KpAdd,
+    KeyCode::KpEnter,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Space,
+    KeyCode::LeftShift,
+    KeyCode::RightShift,
+    KeyCode::LeftControl,
+    KeyCode::RightControl,
+    KeyCode::LeftAlt,
+    KeyCode::RightAlt,
+    KeyCode::Apostrophe,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::Semicolon,
+    KeyCode::LeftBracket,
+    KeyCode::RightBracket,
+    KeyCode::Backslash,
+    KeyCode::GraveAccent,
+    KeyCode::Insert,
+    KeyCode::Delete,
+    KeyCode::Home,
+    KeyCode::End,
+];
+
+// something a player can do with a key
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    Up,
+    Left,
+    Down,
+    Right,
+    Fire,
+}
+
+impl Action {
+    pub const ALL: [Action; 5] = [
+        Action::Up,
+        Action::Left,
+        Action::Down,
+        Action::Right,
+        Action::Fire,
+    ];
+}
+
+// two bindings sharing a key, which would make both actions happen at once
+pub struct Conflict {
+    pub key: KeyCode,
+    pub first: (usize, Action),
+    pub second: (usize, Action),
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ((first_player, first_action), (second_player, second_action)) =
+            (self.first, self.second);
+        write!(
+            f,
+            "{} is bound to {first_action:?} of player {first_player} and {second_action:?} of player {second_player}",
+            key_name(self.key)
+        )
+    }
+}
+
+// how the bindings are stored, keys by name so the file can be edited by hand
+#[derive(Serialize, Deserialize)]
+struct StoredKeymaps {
+    players: Vec<BTreeMap<Action, String>>,
+}
+
+// Keymaps holds the key bound to each action of every player sharing the keyboard
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keymaps {
+    players: [[KeyCode; Action::ALL.len()]; MAX_LOCAL_PLAYERS],
+}
+
+impl Default for Keymaps {
+    fn default() -> Self {
+        Self {
+            players: [
+                [
+                    KeyCode::W,
+                    KeyCode::A,
+                    KeyCode::S,
+                    KeyCode::D,
+                    KeyCode::Space,
+                ],
+                [
+                    KeyCode::Up,
+                    KeyCode::Left,
+                    KeyCode::Down,
+                    KeyCode::Right,
+                    KeyCode::RightShift,
+                ],
+                [KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::H],
+                [
+                    KeyCode::Kp8,
+                    KeyCode::Kp4,
+                    KeyCode::Kp5,
+                    KeyCode::Kp6,
+                    KeyCode::Kp0,
+                ],
+            ],
+        }
+    }
+}
+
+impl Keymaps {
+    pub fn key(&self, local_player: usize, action: Action) -> KeyCode {
+        self.players[local_player][action as usize]
+    }
+
+    pub fn bind(&mut self, local_player: usize, action: Action, key: KeyCode) {
+        self.players[local_player][action as usize] = key;
+    }
+
+    pub fn is_down(&self, local_player: usize, action: Action) -> bool {
+        self.players
+            .get(local_player)
+            .is_some_and(|keymap| is_key_down(keymap[action as usize]))
+    }
+
+    // e.g. ("W,A,S,D", "Space") for the default keys of the first player
+    pub fn describe(&self, local_player: usize) -> (String, String) {
+        let move_keys = [Action::Up, Action::Left, Action::Down, Action::Right]
+            .map(|action| key_name(self.key(local_player, action)))
+            .join(",");
+        (move_keys, key_name(self.key(local_player, Action::Fire)))
+    }
+
+    // every pair of bindings that share a key
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let bindings: Vec<(usize, Action)> = (0..MAX_LOCAL_PLAYERS)
+            .flat_map(|player| Action::ALL.map(|action| (player, action)))
+            .collect();
+        let mut conflicts = Vec::new();
+        for (i, &first) in bindings.iter().enumerate() {
+            for &second in &bindings[i + 1..] {
+                let key = self.key(first.0, first.1);
+                if key == self.key(second.0, second.1) {
+                    conflicts.push(Conflict { key, first, second });
+                }
+            }
+        }
+        conflicts
+    }
+
+    // the stored bindings, or the default ones if there are none or they cannot be used
+    pub fn load() -> Self {
+        let Some(source) = read_stored() else {
+            return Self::default();
+        };
+        match Self::parse(&source) {
+            Ok(keymaps) => {
+                info!("Loaded the stored key bindings");
+                keymaps
+            }
+            Err(e) => {
+                warn!("Ignoring the stored key bindings: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let stored = StoredKeymaps {
+            players: self
+                .players
+                .iter()
+                .map(|keymap| {
+                    Action::ALL
+                        .iter()
+                        .map(|&action| (action, key_name(keymap[action as usize])))
+                        .collect()
+                })
+                .collect(),
+        };
+        let source = ron::ser::to_string_pretty(&stored, ron::ser::PrettyConfig::default())
+            .expect("Failed to serialize key bindings.");
+        write_stored(&source)
+    }
+
+    // actions missing from the source keep their default key, so older files keep working
+    fn parse(source: &str) -> Result<Self, String> {
+        let stored: StoredKeymaps = ron::from_str(source).map_err(|e| e.to_string())?;
+        let mut keymaps = Self::default();
+        for (player, keymap) in stored.players.iter().enumerate().take(MAX_LOCAL_PLAYERS) {
+            for (&action, name) in keymap {
+                let key = parse_key(name).ok_or_else(|| format!("unknown key {name}"))?;
+                keymaps.bind(player, action, key);
+            }
+        }
+        if let Some(conflict) = keymaps.conflicts().first() {
+            return Err(conflict.to_string());
+        }
+        Ok(keymaps)
+    }
+}
+
+pub fn is_bindable(key: KeyCode) -> bool {
+    BINDABLE_KEYS.contains(&key)
+}
+
+pub fn key_name(key: KeyCode) -> String {
+    format!("{key:?}")
+}
+
+fn parse_key(name: &str) -> Option<KeyCode> {
+    BINDABLE_KEYS
+        .iter()
+        .copied()
+        .find(|&key| key_name(key) == name)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_stored() -> Option<String> {
+    std::fs::read_to_string(KEYMAPS_PATH).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_stored(source: &str) -> Result<(), String> {
+    std::fs::write(KEYMAPS_PATH, source).map_err(|e| format!("Could not write {KEYMAPS_PATH}: {e}"))
+}
+
+// browsers do not let us write files, so the bindings go to the localStorage of the page
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_stored() -> Option<String> {
+    local_storage()?.get_item(KEYMAPS_STORAGE_KEY).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_stored(source: &str) -> Result<(), String> {
+    local_storage()
+        .ok_or("The browser does not allow storing settings.")?
+        .set_item(KEYMAPS_STORAGE_KEY, source)
+        .map_err(|e| format!("Could not store the key bindings: {e:?}"))
+}
@@ -0,0 +1,12 @@
+//! The deterministic part of the demo, shared between the windowed demo and the headless runner.
+//! Nothing in here may depend on rendering or on polling input devices.
+
+pub mod bot;
+pub mod desync;
+pub mod fixed;
+pub mod map;
+pub mod net;
+pub mod replay;
+pub mod rng;
+pub mod settings;
+pub mod sim;
@@ -1,50 +1,169 @@
+use std::path::PathBuf;
+
+use ggrs_demo::map::{available_maps, Map};
+use ggrs_demo::sim::Rules;
 use macroquad::prelude::*;
 
+use crate::config::Config;
+use crate::gamepad::Gamepads;
+use crate::handshake::Role;
+use crate::udp_lobby::{UdpRole, DEFAULT_UDP_PORT};
+
+// GGRS only compares checksums from a check distance of 2 on, 1 would test nothing
+pub const MIN_CHECK_DISTANCE: usize = 2;
+pub const MAX_CHECK_DISTANCE: usize = 10;
+pub const MIN_PLAYERS: usize = 2;
+pub const MAX_PLAYERS: usize = 4;
+// a keypad for entering the lobby code on touch screens, '<' deletes a digit and '>' starts
+const KEYPAD: [[char; 3]; 4] = [
+    ['1', '2', '3'],
+    ['4', '5', '6'],
+    ['7', '8', '9'],
+    ['<', '0', '>'],
+];
+const KEYPAD_KEY_SIZE: f32 = 70.0;
+
+// what the user chose to start from the lobby
+pub enum LobbySelection {
+    // join a matchbox room with the given id
+    Online {
+        room_id: String,
+        role: Role,
+        num_players: usize,
+    },
+    // run a local SyncTestSession, rolling back `check_distance` frames every frame
+    SyncTest {
+        check_distance: usize,
+        num_players: usize,
+        bots: usize,
+    },
+    // host or join a session directly over UDP, without a matchbox server
+    Udp {
+        role: UdpRole,
+        num_players: usize,
+    },
+    // play with everyone sharing this machine, without any network
+    Local {
+        num_players: usize,
+        bots: usize,
+    },
+    // play back the given replay, or the most recent one
+    Replay {
+        path: Option<PathBuf>,
+    },
+    // rebind the keys of the local players
+    Controls,
+}
+
 pub struct Lobby {
     text_field: String,
+    check_distance: usize,
+    // GGRS cannot check further back than it may predict
+    max_prediction_window: usize,
+    num_players: usize,
+    // how many of the players are bots when playing locally, at least one player is left for the keyboard
+    bots: usize,
+    spectate: bool,
+    // entering an address for a direct UDP session instead of a lobby code
+    udp_mode: bool,
+    address_field: String,
+    maps: Vec<Map>,
+    selected_map: usize,
+    screen_wrap: bool,
+    error: Option<String>,
     logo: Texture2D,
 }
 
 impl Lobby {
-    pub fn new(logo: Texture2D) -> Self {
+    pub fn new(logo: Texture2D, config: &Config) -> Self {
+        let (maps, errors) = available_maps();
+        for error in errors {
+            warn!("Skipping map {}", error);
+        }
+        // start with the classic arena, if it is there
+        let selected_map = maps
+            .iter()
+            .position(|map| *map == Map::default())
+            .unwrap_or(0);
         Self {
             text_field: "".to_owned(),
+            check_distance: config.check_distance,
+            max_prediction_window: config.max_prediction_window,
+            num_players: config.num_players,
+            bots: config.bots,
+            spectate: false,
+            udp_mode: false,
+            address_field: "".to_owned(),
+            maps,
+            selected_map,
+            screen_wrap: false,
+            error: None,
             logo,
         }
     }
 
-    pub fn run(&mut self) -> Option<String> {
+    // shows an error message until the next selection is made
+    pub fn show_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
+    pub fn selected_map(&self) -> &Map {
+        &self.maps[self.selected_map]
+    }
+
+    pub fn rules(&self) -> Rules {
+        Rules {
+            screen_wrap: self.screen_wrap,
+        }
+    }
+
+    pub fn run(&mut self, gamepads: &mut Gamepads) -> Option<LobbySelection> {
+        if self.udp_mode {
+            return self.run_udp();
+        }
+
+        gamepads.hand_over_pressed();
+
+        // browsers on phones have no keyboard, so they get the keypad
+        let tapped = if cfg!(target_arch = "wasm32") {
+            tapped_keypad_key()
+        } else {
+            None
+        };
+        if let Some(digit) = tapped.filter(char::is_ascii_digit) {
+            self.text_field.push(digit);
+        }
         if is_key_pressed(KeyCode::Key0) {
-            self.text_field.push_str("0");
+            self.text_field.push('0');
         }
         if is_key_pressed(KeyCode::Key1) {
-            self.text_field.push_str("1");
+            self.text_field.push('1');
         }
         if is_key_pressed(KeyCode::Key2) {
-            self.text_field.push_str("2");
+            self.text_field.push('2');
         }
         if is_key_pressed(KeyCode::Key3) {
-            self.text_field.push_str("3");
+            self.text_field.push('3');
         }
         if is_key_pressed(KeyCode::Key4) {
-            self.text_field.push_str("4");
+            self.text_field.push('4');
         }
         if is_key_pressed(KeyCode::Key5) {
-            self.text_field.push_str("5");
+            self.text_field.push('5');
         }
         if is_key_pressed(KeyCode::Key6) {
-            self.text_field.push_str("6");
+            self.text_field.push('6');
         }
         if is_key_pressed(KeyCode::Key7) {
-            self.text_field.push_str("7");
+            self.text_field.push('7');
         }
         if is_key_pressed(KeyCode::Key8) {
-            self.text_field.push_str("8");
+            self.text_field.push('8');
         }
         if is_key_pressed(KeyCode::Key9) {
-            self.text_field.push_str("9");
+            self.text_field.push('9');
         }
-        if is_key_pressed(KeyCode::Backspace) {
+        if is_key_pressed(KeyCode::Backspace) || tapped == Some('<') {
             let mut chars = self.text_field.chars();
             chars.next_back();
             self.text_field = chars.as_str().to_owned();
@@ -54,18 +173,176 @@ impl Lobby {
             self.text_field = self.text_field[0..4].to_owned();
         }
 
-        self.render();
+        if is_key_pressed(KeyCode::Up) {
+            let max_check_distance = MAX_CHECK_DISTANCE
+                .min(self.max_prediction_window.saturating_sub(1))
+                .max(MIN_CHECK_DISTANCE);
+            self.check_distance = (self.check_distance + 1).min(max_check_distance);
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.check_distance = self
+                .check_distance
+                .saturating_sub(1)
+                .max(MIN_CHECK_DISTANCE);
+        }
+
+        if is_key_pressed(KeyCode::P) {
+            self.num_players = if self.num_players < MAX_PLAYERS {
+                self.num_players + 1
+            } else {
+                MIN_PLAYERS
+            };
+            self.bots = self.bots.min(self.num_players - 1);
+        }
+        if is_key_pressed(KeyCode::B) {
+            self.bots = (self.bots + 1) % self.num_players;
+        }
+        if is_key_pressed(KeyCode::M) {
+            self.selected_map = (self.selected_map + 1) % self.maps.len();
+        }
+        if is_key_pressed(KeyCode::W) {
+            self.screen_wrap = !self.screen_wrap;
+        }
+        if is_key_pressed(KeyCode::S) {
+            self.spectate = !self.spectate;
+        }
+        // direct UDP sockets are not available in browsers
+        if is_key_pressed(KeyCode::U) && !cfg!(target_arch = "wasm32") {
+            self.udp_mode = true;
+            self.error = None;
+            clear_input_queue();
+        }
+        let role = if self.spectate {
+            Role::Spectator
+        } else {
+            Role::Player
+        };
 
-        if is_key_pressed(KeyCode::Enter) && self.text_field.len() == 4 {
-            Some(format!("macro{}", self.text_field))
-        } else if is_key_pressed(KeyCode::Enter) && self.text_field.len() == 0 {
-            Some("macro?next=2".to_owned())
+        self.render(gamepads);
+
+        let enter = is_key_pressed(KeyCode::Enter) || tapped == Some('>');
+        let selection = if enter && self.text_field.len() == 4 {
+            Some(LobbySelection::Online {
+                room_id: format!("macro{}", self.text_field),
+                role,
+                num_players: self.num_players,
+            })
+        } else if enter && self.text_field.is_empty() && !self.spectate {
+            Some(LobbySelection::Online {
+                room_id: format!("macro?next={}", self.num_players),
+                role,
+                num_players: self.num_players,
+            })
+        } else if enter {
+            // spectators cannot be matched with random people, they need to know the room
+            let hint = if self.text_field.is_empty() {
+                "Enter a lobby code to spectate."
+            } else {
+                "Lobby codes have 4 digits."
+            };
+            self.error = Some(hint.to_owned());
+            None
+        } else if is_key_pressed(KeyCode::T) {
+            Some(LobbySelection::SyncTest {
+                check_distance: self.check_distance,
+                num_players: self.num_players,
+                bots: self.bots,
+            })
+        } else if is_key_pressed(KeyCode::L) {
+            Some(LobbySelection::Local {
+                num_players: self.num_players,
+                bots: self.bots,
+            })
+        } else if is_key_pressed(KeyCode::R) {
+            Some(LobbySelection::Replay { path: None })
+        } else if is_key_pressed(KeyCode::C) {
+            Some(LobbySelection::Controls)
         } else {
             None
+        };
+
+        if selection.is_some() {
+            self.error = None;
+        }
+        selection
+    }
+
+    fn run_udp(&mut self) -> Option<LobbySelection> {
+        while let Some(c) = get_char_pressed() {
+            if (c.is_ascii_digit() || c == '.' || c == ':') && self.address_field.len() < 21 {
+                self.address_field.push(c);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.address_field.pop();
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            self.udp_mode = false;
+            self.error = None;
+        }
+
+        self.render_udp();
+
+        if !is_key_pressed(KeyCode::Enter) {
+            return None;
+        }
+
+        // an empty field or only a port means hosting, a full address means joining
+        let role = if self.address_field.is_empty() {
+            Some(UdpRole::Host {
+                port: DEFAULT_UDP_PORT,
+            })
+        } else if let Some(port) = self.address_field.strip_prefix(':') {
+            port.parse().ok().map(|port| UdpRole::Host { port })
+        } else {
+            self.address_field
+                .parse()
+                .ok()
+                .map(|host| UdpRole::Join { host })
+        };
+
+        match role {
+            Some(role) => {
+                self.error = None;
+                Some(LobbySelection::Udp {
+                    role,
+                    num_players: self.num_players,
+                })
+            }
+            None => {
+                self.error = Some(format!("Invalid address: {}", self.address_field));
+                None
+            }
+        }
+    }
+
+    fn render_udp(&self) {
+        clear_background(BLACK);
+        draw_text("Direct UDP connection", 20.0, 40.0, 50.0, WHITE);
+        let lines = [
+            format!("- enter the host's address (e.g. 192.168.0.2:{DEFAULT_UDP_PORT}) to join"),
+            format!("- leave empty to host on port {DEFAULT_UDP_PORT}, or enter :PORT to host on another port"),
+            format!("- playing with {} players", self.num_players),
+            format!(
+                "- playing on map {}, screen wrap {}",
+                self.selected_map().name,
+                if self.screen_wrap { "on" } else { "off" }
+            ),
+            "- Then, press ENTER to start, or ESC to go back".to_owned(),
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            draw_text(line, 20.0, 80.0 + i as f32 * 30.0, 30.0, WHITE);
+        }
+
+        let address_str = format!("Address: {}", self.address_field);
+        draw_text(&address_str, 20.0, 290.0, 80.0, YELLOW);
+
+        if let Some(error) = &self.error {
+            draw_text(error, 20.0, 330.0, 30.0, RED);
         }
     }
 
-    fn render(&self) {
+    fn render(&self, gamepads: &Gamepads) {
         clear_background(BLACK);
         let dest_x = screen_width() / 2.0;
         let dest_y = self.logo.height() * (dest_x / self.logo.width());
@@ -103,7 +380,116 @@ impl Lobby {
             WHITE,
         );
 
+        let synctest_str = format!(
+            "- or press T for a local SyncTest (check distance: {}, UP/DOWN to change)",
+            self.check_distance
+        );
+        draw_text(&synctest_str, 20.0, dest_y + 150.0, 30.0, WHITE);
+        draw_text(
+            "- or press L to play locally on one keyboard (C to change the keys), or R to watch a replay",
+            20.0,
+            dest_y + 180.0,
+            30.0,
+            WHITE,
+        );
+        let spectate_str = format!(
+            "- press S to join a lobby as {} (currently: {})",
+            if self.spectate { "player" } else { "spectator" },
+            if self.spectate { "spectator" } else { "player" }
+        );
+        draw_text(&spectate_str, 20.0, dest_y + 210.0, 30.0, WHITE);
+        let players_str = format!(
+            "- press P to change the number of players (currently: {}), B for bots in local games ({})",
+            self.num_players, self.bots
+        );
+        draw_text(&players_str, 20.0, dest_y + 240.0, 30.0, WHITE);
+        let map_str = format!(
+            "- press M to change the map (currently: {}), W to toggle screen wrap ({})",
+            self.selected_map().name,
+            if self.screen_wrap { "on" } else { "off" }
+        );
+        draw_text(&map_str, 20.0, dest_y + 270.0, 30.0, WHITE);
+        if !cfg!(target_arch = "wasm32") {
+            draw_text(
+                "- press U to connect directly over UDP, without a matchbox server",
+                20.0,
+                dest_y + 300.0,
+                30.0,
+                WHITE,
+            );
+        }
+
+        // only mention controllers once one is plugged in
+        let controllers: Vec<String> = (0..self.num_players)
+            .filter_map(|local_player| {
+                let name = gamepads.name(local_player)?;
+                Some(format!("player {local_player}: {name}"))
+            })
+            .collect();
+        if !controllers.is_empty() {
+            let controllers_str = format!(
+                "- controllers ({}), press START to hand one to the next player",
+                controllers.join(", ")
+            );
+            draw_text(&controllers_str, 20.0, dest_y + 330.0, 30.0, WHITE);
+        }
+
         let lobby_code_str = format!("Lobby Code: {}", self.text_field);
-        draw_text(&lobby_code_str, 20.0, dest_y + 200.0, 80.0, YELLOW);
+        draw_text(&lobby_code_str, 20.0, dest_y + 370.0, 80.0, YELLOW);
+
+        if cfg!(target_arch = "wasm32") {
+            render_keypad();
+        }
+
+        if let Some(error) = &self.error {
+            draw_text(error, 20.0, dest_y + 410.0, 30.0, RED);
+        }
+    }
+}
+
+// the top left corner of the keypad, in the bottom right corner of the screen
+fn keypad_origin() -> Vec2 {
+    vec2(
+        screen_width() - KEYPAD[0].len() as f32 * KEYPAD_KEY_SIZE - 20.0,
+        screen_height() - KEYPAD.len() as f32 * KEYPAD_KEY_SIZE - 20.0,
+    )
+}
+
+// taps arrive as mouse clicks, macroquad simulates those for touches
+fn tapped_keypad_key() -> Option<char> {
+    if !is_mouse_button_pressed(MouseButton::Left) {
+        return None;
+    }
+    let (x, y) = mouse_position();
+    let offset = (vec2(x, y) - keypad_origin()) / KEYPAD_KEY_SIZE;
+    if offset.x < 0.0 || offset.y < 0.0 {
+        return None;
+    }
+    let row = KEYPAD.get(offset.y as usize)?;
+    row.get(offset.x as usize).copied()
+}
+
+fn render_keypad() {
+    let origin = keypad_origin();
+    for (row, keys) in KEYPAD.iter().enumerate() {
+        for (column, &key) in keys.iter().enumerate() {
+            let x = origin.x + column as f32 * KEYPAD_KEY_SIZE;
+            let y = origin.y + row as f32 * KEYPAD_KEY_SIZE;
+            let size = KEYPAD_KEY_SIZE - 6.0;
+            draw_rectangle_lines(x, y, size, size, 2.0, WHITE);
+            let label = match key {
+                '<' => "DEL".to_owned(),
+                '>' => "GO".to_owned(),
+                digit => digit.to_string(),
+            };
+            let text_size = measure_text(&label, None, 40, 1.0);
+            draw_text(
+                &label,
+                x + (size - text_size.width) / 2.0,
+                y + size / 2.0 + 12.0,
+                40.0,
+                YELLOW,
+            );
+        }
     }
 }
@@ -1,46 +1,118 @@
+mod config;
+mod controls_view;
 mod ex_game;
+mod gamepad;
+mod handshake;
+mod keymaps;
 mod lobby;
+mod net_panel;
+mod replay_view;
+mod touch;
+mod udp_lobby;
+
+use std::path::PathBuf;
 
 use async_executor::LocalExecutor;
-use ex_game::{FrameStatus, GGRSConfig, Game};
-use ggrs::{GgrsError, P2PSession, PlayerType, SessionBuilder, SessionState};
+use ex_game::{FrameStatus, Game};
+use ggrs::{
+    GgrsError, P2PSession, PlayerType, SessionState, SpectatorSession, SyncTestSession,
+    UdpNonBlockingSocket,
+};
+use ggrs_demo::map::Map;
+use ggrs_demo::net::{ConditionedSocket, MatchboxSocket, NullSocket, PeerAddr, UdpDirectSocket};
+use ggrs_demo::replay::{latest_replay_path, Replay, ReplayPlayer};
+use ggrs_demo::rng::random_seed;
+use ggrs_demo::settings::SessionSettings;
+use ggrs_demo::sim::{GGRSConfig, Rules, FPS};
 use instant::{Duration, Instant};
 use macroquad::prelude::*;
-use matchbox_socket::WebRtcSocket;
+use matchbox_socket::{MultipleChannels, PeerId, WebRtcSocket};
 
+use crate::config::Config;
+use crate::controls_view::ControlsView;
 use crate::ex_game::ConnectionStatus;
-use crate::lobby::Lobby;
+use crate::gamepad::Gamepads;
+use crate::handshake::{session_seed, Handshake, PeerInfo, Role, GGRS_CHANNEL};
+use crate::keymaps::Keymaps;
+use crate::lobby::{Lobby, LobbySelection};
+use crate::net_panel::NetPanel;
+use crate::replay_view::ReplayView;
+use crate::touch::TouchControls;
+use crate::udp_lobby::{UdpLobby, UdpPlayers, UdpRole};
 
-const NUM_PLAYERS: usize = 2;
-const MATCHBOX_ADDR: &str = "ws://127.0.0.1:3536";
-const FPS: f64 = 60.0;
+// a spectator this many frames behind the host starts catching up
+const SPECTATOR_MAX_FRAMES_BEHIND: usize = 10;
+const SPECTATOR_CATCHUP_SPEED: usize = 2;
 
 enum DemoState {
     Lobby,
     Connecting,
+    ConnectingUdp,
     Game,
+    Spectating,
+    SyncTest,
+    Replay,
+    Controls,
 }
 
 struct GGRSDemo<'a> {
     state: DemoState,
+    settings: SessionSettings,
+    matchbox_addr: String,
+    // frame status, checksums and connection stats on top of the game
+    debug_overlay: bool,
+    // started right away instead of waiting for a selection in the lobby
+    autostart: Option<LobbySelection>,
     executor: LocalExecutor<'a>,
-    socket: Option<WebRtcSocket>,
+    socket: Option<WebRtcSocket<MultipleChannels>>,
+    handshake: Option<Handshake>,
+    udp_lobby: Option<UdpLobby>,
     session: Option<P2PSession<GGRSConfig>>,
+    spectator_session: Option<SpectatorSession<GGRSConfig>>,
+    synctest_session: Option<SyncTestSession<GGRSConfig>>,
     lobby: Lobby,
+    net_panel: NetPanel,
+    gamepads: Gamepads,
+    touch: TouchControls,
+    keymaps: Keymaps,
     game: Game,
+    replay_view: Option<ReplayView>,
+    controls_view: Option<ControlsView>,
     last_update: Instant,
     accumulator: Duration,
 }
 
 impl<'a> GGRSDemo<'a> {
-    fn new(logo: Texture2D) -> Self {
+    fn new(logo: Texture2D, config: Config) -> Self {
+        let settings = SessionSettings {
+            num_players: config.num_players,
+            max_prediction_window: config.max_prediction_window,
+            input_delay: config.input_delay,
+            seed: random_seed(),
+            map: Map::default(),
+            rules: Rules::default(),
+        };
         Self {
             state: DemoState::Lobby,
+            game: Game::new(&settings),
+            settings,
+            matchbox_addr: config.matchbox_addr.clone(),
+            debug_overlay: config.debug_overlay,
+            autostart: config.selection(),
             executor: LocalExecutor::new(),
             socket: None,
+            handshake: None,
+            udp_lobby: None,
             session: None,
-            game: Game::new(NUM_PLAYERS),
-            lobby: Lobby::new(logo),
+            spectator_session: None,
+            synctest_session: None,
+            lobby: Lobby::new(logo, &config),
+            net_panel: NetPanel::new(),
+            gamepads: Gamepads::new(),
+            touch: TouchControls::new(),
+            keymaps: Keymaps::load(),
+            replay_view: None,
+            controls_view: None,
             last_update: Instant::now(),
             accumulator: Duration::ZERO,
         }
@@ -49,82 +121,340 @@ impl<'a> GGRSDemo<'a> {
     async fn run(&mut self) {
         loop {
             clear_background(BLACK);
+            self.gamepads.update();
+            self.touch.update();
             match &mut self.state {
                 DemoState::Lobby => self.run_lobby(),
                 DemoState::Connecting => self.run_connecting(),
+                DemoState::ConnectingUdp => self.run_connecting_udp(),
                 DemoState::Game => self.run_game(),
+                DemoState::Spectating => self.run_spectating(),
+                DemoState::SyncTest => self.run_synctest(),
+                DemoState::Replay => self.run_replay(),
+                DemoState::Controls => self.run_controls(),
             }
             next_frame().await;
         }
     }
 
     fn run_lobby(&mut self) {
-        if let Some(room_id) = self.lobby.run() {
-            info!("Constructing socket...");
-            let room_url = format!("{MATCHBOX_ADDR}/{room_id}");
-            let (socket, message_loop) = WebRtcSocket::new_ggrs(room_url);
-            self.socket = Some(socket);
-            let task = self.executor.spawn(message_loop);
-            task.detach();
-            self.state = DemoState::Connecting;
+        let selection = match self.autostart.take() {
+            Some(selection) => Some(selection),
+            None => self.lobby.run(&mut self.gamepads),
+        };
+        // a replay brings its own map and rules
+        if !matches!(
+            selection,
+            None | Some(LobbySelection::Replay { .. } | LobbySelection::Controls)
+        ) {
+            self.settings.map = self.lobby.selected_map().clone();
+            self.settings.rules = self.lobby.rules();
+        }
+        match selection {
+            Some(LobbySelection::Online {
+                room_id,
+                role,
+                num_players,
+            }) => {
+                info!("Constructing socket...");
+                let room_url = format!("{}/{room_id}", self.matchbox_addr);
+                let (socket, message_loop) = WebRtcSocket::builder(room_url)
+                    .add_ggrs_channel()
+                    .add_reliable_channel()
+                    .build();
+                self.socket = Some(socket);
+                self.handshake = Some(Handshake::new(PeerInfo {
+                    role,
+                    num_players,
+                    map_name: self.settings.map.name.clone(),
+                    map_hash: self.settings.map.hash(),
+                    rules: self.settings.rules,
+                }));
+                let task = self.executor.spawn(message_loop);
+                task.detach();
+                self.state = DemoState::Connecting;
+            }
+            Some(LobbySelection::SyncTest {
+                check_distance,
+                num_players,
+                bots,
+            }) => {
+                self.settings.num_players = num_players;
+                self.start_synctest(check_distance, bots)
+            }
+            Some(LobbySelection::Udp { role, num_players }) => {
+                self.settings.num_players = num_players;
+                self.start_udp_lobby(role);
+            }
+            Some(LobbySelection::Local { num_players, bots }) => {
+                self.settings.num_players = num_players;
+                self.start_local_session(bots);
+            }
+            Some(LobbySelection::Replay { path }) => self.start_replay(path),
+            Some(LobbySelection::Controls) => {
+                self.controls_view = Some(ControlsView::new(self.keymaps.clone()));
+                self.state = DemoState::Controls;
+            }
+            None => (),
+        }
+    }
+
+    fn start_udp_lobby(&mut self, role: UdpRole) {
+        match UdpLobby::new(role, &self.settings) {
+            Ok(udp_lobby) => {
+                self.udp_lobby = Some(udp_lobby);
+                self.state = DemoState::ConnectingUdp;
+            }
+            Err(e) => {
+                error!("Could not open UDP socket: {}", e);
+                self.lobby
+                    .show_error(format!("Could not open UDP socket: {e}"));
+            }
+        }
+    }
+
+    fn run_connecting_udp(&mut self) {
+        let udp_lobby = self
+            .udp_lobby
+            .as_mut()
+            .expect("Should only be in UDP connecting state if there exists a UDP lobby.");
+
+        let players = udp_lobby.update();
+        udp_lobby.render();
+
+        if let Some(players) = players {
+            // the lobby socket has to be closed before GGRS can bind the same port
+            self.udp_lobby = None;
+            self.start_udp_session(players);
+            return;
+        }
+
+        // user can abort
+        if is_key_pressed(KeyCode::Escape) {
+            self.state = DemoState::Lobby;
+            self.udp_lobby = None;
+        }
+    }
+
+    fn start_udp_session(&mut self, udp_players: UdpPlayers) {
+        let socket = match UdpNonBlockingSocket::bind_to_port(udp_players.port) {
+            Ok(socket) => socket,
+            Err(e) => {
+                error!("Could not bind UDP port {}: {e}", udp_players.port);
+                self.lobby
+                    .show_error(format!("Could not bind UDP port {}: {e}", udp_players.port));
+                self.state = DemoState::Lobby;
+                return;
+            }
+        };
+
+        // create a new game
+        info!("Starting new game over UDP...");
+        self.settings.seed = udp_players.seed;
+        self.game = Game::new(&self.settings);
+        self.game.set_keymaps(self.keymaps.clone());
+
+        // create a new ggrs session
+        let mut sess_build = self.settings.session_builder();
+        for (handle, player_type) in udp_players.players.into_iter().enumerate() {
+            if matches!(player_type, PlayerType::Local) {
+                self.game
+                    .set_connection_status(vec![handle], ConnectionStatus::Local);
+            }
+            sess_build = sess_build
+                .add_player(player_type, handle)
+                .expect("Invalid player added.");
+        }
+        let sess = sess_build
+            .start_p2p_session(ConditionedSocket::new(
+                UdpDirectSocket(socket),
+                self.net_panel.conditions(),
+            ))
+            .expect("Session could not be created.");
+        self.session = Some(sess);
+        self.state = DemoState::Game;
+
+        // record the confirmed inputs so the match can be replayed later
+        self.game.start_recording(&self.settings);
+
+        // reset time variables for frame ticks
+        self.last_update = Instant::now();
+        self.accumulator = Duration::ZERO;
+    }
+
+    fn start_local_session(&mut self, bots: usize) {
+        info!("Starting local game with {} bot(s)...", bots);
+        let num_players = self.settings.num_players;
+        self.settings.seed = random_seed();
+        self.game = Game::new(&self.settings);
+        self.game.set_keymaps(self.keymaps.clone());
+        self.game
+            .set_connection_status((0..num_players).collect(), ConnectionStatus::Local);
+        self.game.add_bots(bots);
+
+        // all players share this machine, so the session never has to talk to anyone
+        let mut sess_build = self.settings.session_builder();
+        for handle in 0..num_players {
+            sess_build = sess_build
+                .add_player(PlayerType::Local, handle)
+                .expect("Invalid player added.");
+        }
+        let sess = sess_build
+            .start_p2p_session(NullSocket)
+            .expect("Session could not be created.");
+        self.session = Some(sess);
+        self.state = DemoState::Game;
+
+        // record the confirmed inputs so the match can be replayed later
+        self.game.start_recording(&self.settings);
+
+        // reset time variables for frame ticks
+        self.last_update = Instant::now();
+        self.accumulator = Duration::ZERO;
+    }
+
+    fn start_replay(&mut self, path: Option<PathBuf>) {
+        let Some(path) = path.or_else(latest_replay_path) else {
+            self.lobby.show_error("No replay found.".to_owned());
+            return;
+        };
+        match Replay::load(&path) {
+            Ok(replay) => {
+                info!("Playing replay {}...", path.display());
+                self.replay_view = Some(ReplayView::new(ReplayPlayer::new(replay)));
+                self.state = DemoState::Replay;
+            }
+            Err(e) => {
+                error!("Could not load replay {}: {e}", path.display());
+                self.lobby
+                    .show_error(format!("Could not load {}: {e}", path.display()));
+            }
         }
     }
 
+    fn start_synctest(&mut self, check_distance: usize, bots: usize) {
+        info!(
+            "Starting SyncTest with check distance {} and {} bot(s)...",
+            check_distance, bots
+        );
+        // in a SyncTestSession, all players are local
+        let sess = match self
+            .settings
+            .session_builder()
+            .with_check_distance(check_distance)
+            .start_synctest_session()
+        {
+            Ok(sess) => sess,
+            Err(e) => {
+                error!("Could not start SyncTest: {}", e);
+                self.lobby
+                    .show_error(format!("Could not start SyncTest: {e}"));
+                self.state = DemoState::Lobby;
+                return;
+            }
+        };
+        self.synctest_session = Some(sess);
+
+        let num_players = self.settings.num_players;
+        self.settings.seed = random_seed();
+        self.game = Game::new(&self.settings);
+        self.game.set_keymaps(self.keymaps.clone());
+        self.game
+            .set_connection_status((0..num_players).collect(), ConnectionStatus::Local);
+        self.game.add_bots(bots);
+        self.state = DemoState::SyncTest;
+
+        // reset time variables for frame ticks
+        self.last_update = Instant::now();
+        self.accumulator = Duration::ZERO;
+    }
+
     fn run_connecting(&mut self) {
         let socket = self
             .socket
             .as_mut()
             .expect("Should only be in connecting state if there exists a socket.");
+        let handshake = self
+            .handshake
+            .as_mut()
+            .expect("Should only be in connecting state if there exists a handshake.");
 
         self.executor.try_tick();
 
-        // Update peers and count connected ones
-        let _peer_updates = socket.update_peers();
-        let connected_peers_count = socket.connected_peers().count();
+        // greet new peers and learn whether they play or spectate
+        handshake.update(socket);
+        let our_id = socket.id();
+        let players = handshake.players(our_id);
+        let spectators = handshake.spectators();
+        let mismatched_players = handshake.mismatched_players();
+        let mismatched_maps = handshake.mismatched_maps();
+        let mismatched_rules = handshake.mismatched_rules();
+        let handshake_complete = handshake.is_complete(socket);
+        let role = handshake.role();
+        let num_players = handshake.num_players();
+        let rejection = handshake.rejection().map(str::to_owned);
 
-        let info_str = format!(
-            "Waiting for {} more player(s)...",
-            NUM_PLAYERS - 1 - connected_peers_count
-        );
+        let info_str = if players.len() > num_players {
+            format!("Too many players in this room, only {num_players} can play.")
+        } else {
+            format!(
+                "Waiting for {} more player(s)...",
+                num_players - players.len()
+            )
+        };
         draw_text(&info_str, 20.0, 20.0, 30.0, WHITE);
 
-        // if we have enough players - we assume there to be only one local player
-        if connected_peers_count >= NUM_PLAYERS - 1 {
-            // create a new game
-            info!("Starting new game...");
-            self.game = Game::new(NUM_PLAYERS);
-            self.state = DemoState::Game;
-
-            // create a new ggrs session
-            let mut sess_build = SessionBuilder::<GGRSConfig>::new()
-                .with_num_players(NUM_PLAYERS)
-                .with_max_prediction_window(12)
-                .expect("Invalid prediction window")
-                .with_fps(FPS as usize)
-                .expect("Invalid FPS")
-                .with_input_delay(2);
-
-            // add players
-            for (i, player_type) in socket.players().iter().enumerate() {
-                let ggrs_player_type = match player_type {
-                    ggrs::PlayerType::Local => PlayerType::Local,
-                    ggrs::PlayerType::Remote(peer_id) => PlayerType::Remote(*peer_id),
-                    ggrs::PlayerType::Spectator(peer_id) => PlayerType::Spectator(*peer_id),
-                };
-                sess_build = sess_build
-                    .add_player(ggrs_player_type, i)
-                    .expect("Invalid player added.");
-                if matches!(player_type, ggrs::PlayerType::Local) {
-                    self.game
-                        .set_connection_status(vec![i], ConnectionStatus::Local);
-                }
-            }
+        // show who occupies which player slot
+        for slot in 0..num_players {
+            let slot_str = match players.get(slot) {
+                Some(peer) if Some(*peer) == our_id => format!("Player {slot}: you"),
+                Some(peer) => format!("Player {slot}: {peer}"),
+                None => format!("Player {slot}: waiting..."),
+            };
+            draw_text(&slot_str, 20.0, 60.0 + slot as f32 * 20.0, 30.0, WHITE);
+        }
+        let spectators_str = format!("Spectators: {}", spectators.len());
+        let y = 60.0 + num_players as f32 * 20.0;
+        draw_text(&spectators_str, 20.0, y + 20.0, 30.0, WHITE);
+
+        // peers that chose a different number of players can never play together
+        for (i, (peer, peer_num_players)) in mismatched_players.iter().enumerate() {
+            let mismatch_str =
+                format!("Player {peer} wants to play with {peer_num_players} players");
+            draw_text(&mismatch_str, 20.0, y + 60.0 + i as f32 * 20.0, 30.0, RED);
+        }
+        // neither can peers that would simulate different arenas
+        let y = y + mismatched_players.len() as f32 * 20.0;
+        for (i, (peer, map_name)) in mismatched_maps.iter().enumerate() {
+            let mismatch_str = format!("Peer {peer} wants to play on map {map_name}");
+            draw_text(&mismatch_str, 20.0, y + 60.0 + i as f32 * 20.0, 30.0, RED);
+        }
+        let y = y + mismatched_maps.len() as f32 * 20.0;
+        for (i, (peer, rules)) in mismatched_rules.iter().enumerate() {
+            let mismatch_str = format!("Peer {peer} wants to play with {rules:?}");
+            draw_text(&mismatch_str, 20.0, y + 60.0 + i as f32 * 20.0, 30.0, RED);
+        }
 
-            // start the GGRS session
-            let sess = sess_build
-                .start_p2p_session(self.socket.take().unwrap())
-                .expect("Session could not be created.");
-            self.session = Some(sess);
+        // the players of this room are already in a match
+        if let Some(reason) = rejection {
+            self.leave_room();
+            self.lobby.show_error(reason);
+            return;
+        }
+
+        // once everyone announced their role and we have enough players, the game starts
+        if handshake_complete
+            && mismatched_players.is_empty()
+            && mismatched_maps.is_empty()
+            && mismatched_rules.is_empty()
+            && players.len() == num_players
+        {
+            self.settings.num_players = num_players;
+            self.settings.seed = session_seed(&players);
+            match role {
+                Role::Player => self.start_p2p_session(&players, &spectators),
+                Role::Spectator => self.start_spectator_session(players[0]),
+            }
 
             // reset time variables for frame ticks
             self.last_update = Instant::now();
@@ -133,12 +463,101 @@ impl<'a> GGRSDemo<'a> {
 
         // user can abort
         if is_key_pressed(KeyCode::Escape) {
-            self.state = DemoState::Lobby;
-            self.socket = None;
-            self.executor = LocalExecutor::new();
+            self.leave_room();
         }
     }
 
+    // disconnects from the matchbox room and goes back to the lobby
+    fn leave_room(&mut self) {
+        self.state = DemoState::Lobby;
+        self.spectator_session = None;
+        self.socket = None;
+        self.handshake = None;
+        self.executor = LocalExecutor::new();
+    }
+
+    fn start_p2p_session(&mut self, players: &[PeerId], spectators: &[PeerId]) {
+        let socket = self.socket.as_mut().unwrap();
+        let our_id = socket
+            .id()
+            .expect("Players are known only after getting an id.");
+
+        // create a new game
+        info!("Starting new game...");
+        let num_players = self.settings.num_players;
+        self.game = Game::new(&self.settings);
+        self.game.set_keymaps(self.keymaps.clone());
+        self.state = DemoState::Game;
+
+        // create a new ggrs session
+        let mut sess_build = self.settings.session_builder();
+
+        // add players, every peer sorts them the same way
+        for (handle, peer) in players.iter().enumerate() {
+            let player_type = if *peer == our_id {
+                self.game
+                    .set_connection_status(vec![handle], ConnectionStatus::Local);
+                PlayerType::Local
+            } else {
+                PlayerType::Remote(PeerAddr::Matchbox(*peer))
+            };
+            sess_build = sess_build
+                .add_player(player_type, handle)
+                .expect("Invalid player added.");
+        }
+
+        // the first player hosts all spectators
+        if players[0] == our_id {
+            for (i, spectator) in spectators.iter().enumerate() {
+                sess_build = sess_build
+                    .add_player(
+                        PlayerType::Spectator(PeerAddr::Matchbox(*spectator)),
+                        num_players + i,
+                    )
+                    .expect("Invalid spectator added.");
+            }
+        }
+
+        // start the GGRS session
+        let channel = socket
+            .take_channel(GGRS_CHANNEL)
+            .expect("GGRS channel already taken.");
+        let sess = sess_build
+            .start_p2p_session(ConditionedSocket::new(
+                MatchboxSocket(channel),
+                self.net_panel.conditions(),
+            ))
+            .expect("Session could not be created.");
+        self.session = Some(sess);
+
+        // record the confirmed inputs so the match can be replayed later
+        self.game.start_recording(&self.settings);
+    }
+
+    fn start_spectator_session(&mut self, host: PeerId) {
+        let socket = self.socket.as_mut().unwrap();
+
+        info!("Spectating game hosted by {}...", host);
+        self.game = Game::new(&self.settings);
+        self.state = DemoState::Spectating;
+
+        let channel = socket
+            .take_channel(GGRS_CHANNEL)
+            .expect("GGRS channel already taken.");
+        let sess = self
+            .settings
+            .session_builder()
+            .with_max_frames_behind(SPECTATOR_MAX_FRAMES_BEHIND)
+            .expect("Invalid max frames behind")
+            .with_catchup_speed(SPECTATOR_CATCHUP_SPEED)
+            .expect("Invalid catchup speed")
+            .start_spectator_session(
+                PeerAddr::Matchbox(host),
+                ConditionedSocket::new(MatchboxSocket(channel), self.net_panel.conditions()),
+            );
+        self.spectator_session = Some(sess);
+    }
+
     fn run_game(&mut self) {
         let sess = self
             .session
@@ -153,6 +572,16 @@ impl<'a> GGRSDemo<'a> {
         // handle GGRS events
         self.game.handle_events(sess);
 
+        // turn away peers that join the room too late, matchbox rooms stay open during the match
+        if let (Some(socket), Some(handshake)) = (self.socket.as_mut(), self.handshake.as_mut()) {
+            handshake.update_in_match(socket);
+        }
+
+        // bots can take over the local players, e.g. to leave a peer running as a soak test
+        if is_key_pressed(KeyCode::F2) {
+            self.game.toggle_autopilot();
+        }
+
         // update network stats
         for handle in sess.remote_player_handles() {
             self.game.connection_info[handle].stats = sess.network_stats(handle).ok();
@@ -160,7 +589,7 @@ impl<'a> GGRSDemo<'a> {
 
         // this is to keep ticks between clients synchronized.
         // if a client is ahead, it will run frames slightly slower to allow catching up
-        let mut fps_delta = 1. / FPS;
+        let mut fps_delta = 1. / FPS as f64;
         if sess.frames_ahead() > 0 {
             fps_delta *= 1.1;
         }
@@ -179,15 +608,22 @@ impl<'a> GGRSDemo<'a> {
 
             // frames are only happening if the sessions are synchronized
             if sess.current_state() == SessionState::Running {
-                // add input for all local players
-                for handle in sess.local_player_handles() {
-                    sess.add_local_input(handle, self.game.local_input(0))
-                        .expect("Invalid player handle"); // we always call game.local_input(0) in order to get WASD inputs.
+                // add input for all local players, the first one always uses WASD
+                let mut local_handles = sess.local_player_handles();
+                local_handles.sort();
+                for (local_player, handle) in local_handles.into_iter().enumerate() {
+                    sess.add_local_input(
+                        handle,
+                        self.game
+                            .input(local_player, handle, &self.gamepads, &self.touch),
+                    )
+                    .expect("Invalid player handle");
                 }
 
                 match sess.advance_frame() {
                     Ok(requests) => {
                         self.game.handle_requests(requests);
+                        self.game.confirm_frames(sess.confirmed_frame());
                         self.game.frame_info = if sess.frames_ahead() > 0 {
                             FrameStatus::Slow
                         } else {
@@ -203,12 +639,217 @@ impl<'a> GGRSDemo<'a> {
         }
 
         self.game.render();
+        if self.debug_overlay {
+            self.game.render_debug();
+        }
+        self.touch.render();
+        self.net_panel.run();
+        self.executor.try_tick();
+    }
+
+    fn run_spectating(&mut self) {
+        let sess = self
+            .spectator_session
+            .as_mut()
+            .expect("Should only be in spectating state if there exists a spectator session.");
+
+        // communicate, receive and send packets
+        self.executor.try_tick();
+        sess.poll_remote_clients();
+        self.executor.try_tick();
+
+        // handle GGRS events
+        self.game.handle_spectator_events(sess);
+
+        // the host turns us away if our hello arrived after it started the session
+        let socket = self
+            .socket
+            .as_mut()
+            .expect("Spectators always use matchbox.");
+        let handshake = self
+            .handshake
+            .as_mut()
+            .expect("Spectators always use matchbox.");
+        handshake.update_in_match(socket);
+        if let Some(reason) = handshake.rejection().map(str::to_owned) {
+            self.leave_room();
+            self.lobby.show_error(reason);
+            return;
+        }
+
+        // update network stats, we only talk to the host
+        let stats = sess.network_stats().ok();
+        for con_info in self.game.connection_info.iter_mut() {
+            con_info.stats = stats;
+        }
+
+        // get delta time from last iteration and accumulate it
+        let fps_delta = 1. / FPS as f64;
+        let delta = Instant::now().duration_since(self.last_update);
+        self.accumulator = self.accumulator.saturating_add(delta);
+        self.last_update = Instant::now();
+
+        // if enough time is accumulated, we run a frame
+        while self.accumulator.as_secs_f64() > fps_delta {
+            // decrease accumulator
+            self.accumulator = self
+                .accumulator
+                .saturating_sub(Duration::from_secs_f64(fps_delta));
+
+            // frames are only happening if the spectator is synchronized with the host
+            if sess.current_state() != SessionState::Running {
+                continue;
+            }
+
+            // the session advances multiple frames at once if it fell too far behind
+            let catchup_speed = if sess.frames_behind_host() > SPECTATOR_MAX_FRAMES_BEHIND {
+                SPECTATOR_CATCHUP_SPEED
+            } else {
+                1
+            };
+
+            match sess.advance_frame() {
+                Ok(requests) => {
+                    self.game.handle_requests(requests);
+                    self.game.frame_info = FrameStatus::Spectating {
+                        frames_behind: sess.frames_behind_host(),
+                        catchup_speed,
+                    };
+                }
+                // the host did not send the inputs for the next frame yet
+                Err(GgrsError::PredictionThreshold) => self.game.frame_info = FrameStatus::Halt,
+                Err(GgrsError::SpectatorTooFarBehind) => {
+                    error!("Spectator fell too far behind the host to catch up.");
+                    self.game.frame_info = FrameStatus::SpectatorTooFarBehind;
+                }
+                Err(e) => panic!(
+                    "Unknown error happened during SpectatorSession::<_>::advance_frame(): {e}"
+                ),
+            }
+        }
+
+        self.game.render();
+        if self.debug_overlay {
+            self.game.render_debug();
+        }
+        self.net_panel.run();
         self.executor.try_tick();
     }
+
+    fn run_synctest(&mut self) {
+        let sess = self
+            .synctest_session
+            .as_mut()
+            .expect("Should only be in synctest state if there exists a synctest session.");
+
+        // get delta time from last iteration and accumulate it
+        let fps_delta = 1. / FPS as f64;
+        let delta = Instant::now().duration_since(self.last_update);
+        self.accumulator = self.accumulator.saturating_add(delta);
+        self.last_update = Instant::now();
+
+        // if enough time is accumulated, we run a frame
+        while self.accumulator.as_secs_f64() > fps_delta {
+            // decrease accumulator
+            self.accumulator = self
+                .accumulator
+                .saturating_sub(Duration::from_secs_f64(fps_delta));
+
+            // after a mismatch, the session will keep reporting it, so we stop advancing
+            if matches!(self.game.frame_info, FrameStatus::Mismatch { .. }) {
+                continue;
+            }
+
+            // add input for all players, each with their own keys or bot
+            for handle in 0..sess.num_players() {
+                sess.add_local_input(
+                    handle,
+                    self.game.input(handle, handle, &self.gamepads, &self.touch),
+                )
+                .expect("Invalid player handle");
+            }
+
+            match sess.advance_frame() {
+                Ok(requests) => self.game.handle_requests(requests),
+                Err(GgrsError::MismatchedChecksum {
+                    current_frame,
+                    mismatched_frames,
+                }) => {
+                    error!(
+                        "SyncTest detected a checksum mismatch on frame {}, mismatched frames: {:?}",
+                        current_frame, mismatched_frames
+                    );
+                    self.game.frame_info = FrameStatus::Mismatch {
+                        current_frame,
+                        mismatched_frames,
+                    };
+                }
+                Err(e) => panic!(
+                    "Unknown error happened during SyncTestSession::<_>::advance_frame(): {e}"
+                ),
+            }
+        }
+
+        self.game.render();
+        if self.debug_overlay {
+            self.game.render_debug();
+        }
+        self.touch.render();
+
+        if is_key_pressed(KeyCode::F2) {
+            self.game.toggle_autopilot();
+        }
+
+        // user can return to the lobby
+        if is_key_pressed(KeyCode::Escape) {
+            self.state = DemoState::Lobby;
+            self.synctest_session = None;
+        }
+    }
+
+    fn run_replay(&mut self) {
+        let replay_view = self
+            .replay_view
+            .as_mut()
+            .expect("Should only be in replay state if there exists a replay view.");
+        replay_view.run();
+
+        // user can return to the lobby
+        if is_key_pressed(KeyCode::Escape) {
+            self.state = DemoState::Lobby;
+            self.replay_view = None;
+        }
+    }
+
+    fn run_controls(&mut self) {
+        let controls_view = self
+            .controls_view
+            .as_mut()
+            .expect("Should only be in controls state if there exists a controls view.");
+        let Some(keymaps) = controls_view.run() else {
+            return;
+        };
+
+        if let Err(e) = keymaps.save() {
+            error!("{}", e);
+            self.lobby.show_error(e);
+        }
+        self.keymaps = keymaps;
+        self.state = DemoState::Lobby;
+        self.controls_view = None;
+    }
+}
+
+fn main() {
+    // the options are read before opening the window, so --help and mistakes work without a display
+    #[cfg(not(target_arch = "wasm32"))]
+    let config = Config::from_env_and_args();
+    #[cfg(target_arch = "wasm32")]
+    let config = Config::from_url();
+    macroquad::Window::new("GGRS Demo", run(config));
 }
 
-#[macroquad::main("GGRS Demo")]
-async fn main() {
+async fn run(config: Config) {
     let logo: Texture2D = load_texture("ggrs_logo.png").await.unwrap();
-    GGRSDemo::new(logo).run().await;
+    GGRSDemo::new(logo, config).run().await;
 }
@@ -0,0 +1,150 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fixed::Fixed;
+use crate::rng::seed_from;
+use crate::sim::{ARENA_HEIGHT, ARENA_WIDTH};
+
+pub const MAP_DIR: &str = "maps";
+
+// the maps shipped with the demo, used when the map directory cannot be read, e.g. in the browser
+const BUILTIN_MAPS: [&str; 3] = [
+    include_str!("../maps/empty.ron"),
+    include_str!("../maps/pillars.ron"),
+    include_str!("../maps/corridors.ron"),
+];
+
+/// An axis-aligned rectangle ships bounce off and bullets cannot pass, in arena units.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Obstacle {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Obstacle {
+    pub fn min(&self) -> (Fixed, Fixed) {
+        (Fixed::from_int(self.x), Fixed::from_int(self.y))
+    }
+
+    pub fn max(&self) -> (Fixed, Fixed) {
+        (
+            Fixed::from_int(self.x + self.width),
+            Fixed::from_int(self.y + self.height),
+        )
+    }
+
+    pub fn contains(&self, x: Fixed, y: Fixed) -> bool {
+        let ((min_x, min_y), (max_x, max_y)) = (self.min(), self.max());
+        (min_x..=max_x).contains(&x) && (min_y..=max_y).contains(&y)
+    }
+
+    /// whether the obstacle has a size and lies completely inside the arena
+    pub fn is_inside_arena(&self) -> bool {
+        span_inside(self.x, self.width, ARENA_WIDTH)
+            && span_inside(self.y, self.height, ARENA_HEIGHT)
+    }
+}
+
+// computed with i64, so values far outside the arena cannot overflow the conversion to `Fixed`
+fn span_inside(start: i32, size: i32, limit: Fixed) -> bool {
+    let end = (start as i64 + size as i64) * Fixed::ONE.0 as i64;
+    start >= 0 && size > 0 && end <= limit.0 as i64
+}
+
+/// A map is written in RON, e.g. `(name: "Box", obstacles: [(x: 10, y: 10, width: 50, height: 50)])`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Map {
+    pub name: String,
+    pub obstacles: Vec<Obstacle>,
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    Invalid(ron::error::SpannedError),
+    OutsideArena(Obstacle),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io(e) => write!(f, "could not read map: {e}"),
+            MapError::Invalid(e) => write!(f, "invalid map: {e}"),
+            MapError::OutsideArena(obstacle) => write!(
+                f,
+                "obstacle {obstacle:?} must have a positive size and lie inside the {}x{} arena",
+                ARENA_WIDTH.to_f64(),
+                ARENA_HEIGHT.to_f64()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl Default for Map {
+    fn default() -> Self {
+        Self {
+            name: "Empty".to_owned(),
+            obstacles: Vec::new(),
+        }
+    }
+}
+
+impl Map {
+    pub fn parse(source: &str) -> Result<Self, MapError> {
+        let map: Self = ron::from_str(source).map_err(MapError::Invalid)?;
+        match map
+            .obstacles
+            .iter()
+            .find(|obstacle| !obstacle.is_inside_arena())
+        {
+            Some(obstacle) => Err(MapError::OutsideArena(*obstacle)),
+            None => Ok(map),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, MapError> {
+        let source = std::fs::read_to_string(path).map_err(MapError::Io)?;
+        Self::parse(&source)
+    }
+
+    /// identifies the map, so peers can make sure they play on the same one
+    pub fn hash(&self) -> u64 {
+        let bytes = bincode::serialize(self).expect("Failed to serialize map.");
+        seed_from(&[&bytes])
+    }
+}
+
+/// all maps inside `MAP_DIR` sorted by name, or the built-in maps if there are none.
+/// Also returns a description of every map file that could not be loaded.
+pub fn available_maps() -> (Vec<Map>, Vec<String>) {
+    let mut maps = Vec::new();
+    let mut errors = Vec::new();
+    let paths = std::fs::read_dir(MAP_DIR)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ron"));
+    for path in paths {
+        match Map::load(&path) {
+            Ok(map) => maps.push(map),
+            Err(e) => errors.push(format!("{}: {e}", path.display())),
+        }
+    }
+
+    if maps.is_empty() {
+        maps = BUILTIN_MAPS
+            .iter()
+            .map(|source| Map::parse(source).expect("Built-in map is invalid."))
+            .collect();
+    }
+    maps.sort_by(|a, b| a.name.cmp(&b.name));
+    (maps, errors)
+}
@@ -0,0 +1,203 @@
+use std::hash::Hash;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use ggrs::{Message, NonBlockingSocket, UdpNonBlockingSocket};
+use instant::{Duration, Instant, SystemTime};
+use matchbox_socket::{PeerId, WebRtcChannel};
+
+/// Identifies a remote peer, independent of the transport used to reach it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PeerAddr {
+    Matchbox(PeerId),
+    Udp(SocketAddr),
+}
+
+/// Carries GGRS messages over a matchbox WebRTC channel.
+pub struct MatchboxSocket(pub WebRtcChannel);
+
+impl NonBlockingSocket<PeerAddr> for MatchboxSocket {
+    fn send_to(&mut self, msg: &Message, addr: &PeerAddr) {
+        match addr {
+            PeerAddr::Matchbox(peer) => self.0.send_to(msg, peer),
+            PeerAddr::Udp(_) => panic!("Cannot reach {addr:?} through matchbox."),
+        }
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(PeerAddr, Message)> {
+        self.0
+            .receive_all_messages()
+            .into_iter()
+            .map(|(peer, msg)| (PeerAddr::Matchbox(peer), msg))
+            .collect()
+    }
+}
+
+/// Carries GGRS messages directly over UDP, without any signaling server.
+pub struct UdpDirectSocket(pub UdpNonBlockingSocket);
+
+impl NonBlockingSocket<PeerAddr> for UdpDirectSocket {
+    fn send_to(&mut self, msg: &Message, addr: &PeerAddr) {
+        match addr {
+            PeerAddr::Udp(socket_addr) => self.0.send_to(msg, socket_addr),
+            PeerAddr::Matchbox(_) => panic!("Cannot reach {addr:?} through UDP."),
+        }
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(PeerAddr, Message)> {
+        self.0
+            .receive_all_messages()
+            .into_iter()
+            .map(|(socket_addr, msg)| (PeerAddr::Udp(socket_addr), msg))
+            .collect()
+    }
+}
+
+/// A socket that is not connected to anything, for sessions that only have local players.
+pub struct NullSocket;
+
+impl<A: Clone + PartialEq + Eq + Hash + Send + Sync> NonBlockingSocket<A> for NullSocket {
+    fn send_to(&mut self, _msg: &Message, _addr: &A) {}
+
+    fn receive_all_messages(&mut self) -> Vec<(A, Message)> {
+        Vec::new()
+    }
+}
+
+/// Network conditions injected by a [`ConditionedSocket`], applied to packets in both directions.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NetworkConditions {
+    /// one-way delay added to every packet, in milliseconds
+    pub latency_ms: u32,
+    /// additional random delay of up to this many milliseconds
+    pub jitter_ms: u32,
+    /// chance of dropping a packet, between 0 and 1
+    pub loss: f32,
+    /// chance of delivering a packet twice, between 0 and 1
+    pub duplication: f32,
+    /// chance of holding a packet back, so packets sent after it overtake it, between 0 and 1
+    pub reordering: f32,
+}
+
+impl NetworkConditions {
+    pub fn is_perfect(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+// how long reordered packets are held back in addition to their normal delay
+const REORDER_DELAY: Duration = Duration::from_millis(50);
+
+struct DelayedPacket {
+    deliver_at: Instant,
+    addr: PeerAddr,
+    msg: Message,
+}
+
+/// Wraps a socket and degrades the connection according to [`NetworkConditions`] that can be
+/// changed while the session is running, to reproduce bad connections locally.
+pub struct ConditionedSocket<S> {
+    inner: S,
+    conditions: Arc<Mutex<NetworkConditions>>,
+    outgoing: Vec<DelayedPacket>,
+    incoming: Vec<DelayedPacket>,
+    rng: u32,
+}
+
+impl<S: NonBlockingSocket<PeerAddr>> ConditionedSocket<S> {
+    pub fn new(inner: S, conditions: Arc<Mutex<NetworkConditions>>) -> Self {
+        // the exact sequence of dropped packets does not matter, so seed from the clock
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        Self {
+            inner,
+            conditions,
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+            rng: seed | 1,
+        }
+    }
+
+    // xorshift32, returns a number between 0 and 1
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as f32 / u32::MAX as f32
+    }
+
+    // decides when, if at all, and how often a packet arrives
+    fn delays(&mut self) -> Vec<Duration> {
+        let conditions = self
+            .conditions
+            .lock()
+            .expect("Network conditions lock poisoned.")
+            .clone();
+        if self.random() < conditions.loss {
+            return Vec::new();
+        }
+        let copies = if self.random() < conditions.duplication {
+            2
+        } else {
+            1
+        };
+        (0..copies)
+            .map(|_| {
+                let jitter = (self.random() * conditions.jitter_ms as f32) as u64;
+                let delay = Duration::from_millis(conditions.latency_ms as u64 + jitter);
+                if self.random() < conditions.reordering {
+                    delay + REORDER_DELAY
+                } else {
+                    delay
+                }
+            })
+            .collect()
+    }
+}
+
+// removes the packets that are due from the queue, in the order they are due
+fn take_due(packets: &mut Vec<DelayedPacket>) -> Vec<DelayedPacket> {
+    let now = Instant::now();
+    let (mut due, delayed): (Vec<_>, Vec<_>) = packets
+        .drain(..)
+        .partition(|packet| packet.deliver_at <= now);
+    *packets = delayed;
+    due.sort_by_key(|packet| packet.deliver_at);
+    due
+}
+
+impl<S: NonBlockingSocket<PeerAddr>> NonBlockingSocket<PeerAddr> for ConditionedSocket<S> {
+    fn send_to(&mut self, msg: &Message, addr: &PeerAddr) {
+        for delay in self.delays() {
+            self.outgoing.push(DelayedPacket {
+                deliver_at: Instant::now() + delay,
+                addr: *addr,
+                msg: msg.clone(),
+            });
+        }
+        for packet in take_due(&mut self.outgoing) {
+            self.inner.send_to(&packet.msg, &packet.addr);
+        }
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(PeerAddr, Message)> {
+        for (addr, msg) in self.inner.receive_all_messages() {
+            for delay in self.delays() {
+                self.incoming.push(DelayedPacket {
+                    deliver_at: Instant::now() + delay,
+                    addr,
+                    msg: msg.clone(),
+                });
+            }
+        }
+        // delayed outgoing packets are also sent when GGRS only polls for a while
+        for packet in take_due(&mut self.outgoing) {
+            self.inner.send_to(&packet.msg, &packet.addr);
+        }
+        take_due(&mut self.incoming)
+            .into_iter()
+            .map(|packet| (packet.addr, packet.msg))
+            .collect()
+    }
+}
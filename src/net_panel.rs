@@ -0,0 +1,118 @@
+use std::sync::{Arc, Mutex};
+
+use ggrs_demo::net::NetworkConditions;
+use macroquad::prelude::*;
+
+// the adjustable parameters, in the order they are listed
+const PARAMETERS: [&str; 5] = ["Latency", "Jitter", "Loss", "Duplication", "Reordering"];
+const MAX_DELAY_MS: u32 = 1000;
+const DELAY_STEP_MS: u32 = 10;
+const CHANCE_STEP: f32 = 0.05;
+// the panel sits below the scoreboard, which takes the top right corner for up to four players
+const PANEL_X_FROM_RIGHT: f32 = 300.0;
+const PANEL_TOP: f32 = 180.0;
+
+// NetPanel is a debug panel to change the simulated network conditions while a session is running.
+// F1 shows or hides it, TAB selects a parameter, - and = decrease and increase it, BACKSPACE resets all.
+pub struct NetPanel {
+    conditions: Arc<Mutex<NetworkConditions>>,
+    visible: bool,
+    selected: usize,
+}
+
+impl NetPanel {
+    pub fn new() -> Self {
+        Self {
+            conditions: Arc::new(Mutex::new(NetworkConditions::default())),
+            visible: false,
+            selected: 0,
+        }
+    }
+
+    // handle shared with the sockets, so changes apply to the running session
+    pub fn conditions(&self) -> Arc<Mutex<NetworkConditions>> {
+        self.conditions.clone()
+    }
+
+    pub fn run(&mut self) {
+        if is_key_pressed(KeyCode::F1) {
+            self.visible = !self.visible;
+        }
+        if !self.visible {
+            // remind that the connection is degraded on purpose
+            let conditions = self
+                .conditions
+                .lock()
+                .expect("Network conditions lock poisoned.");
+            if !conditions.is_perfect() {
+                let x = screen_width() - PANEL_X_FROM_RIGHT;
+                let y = PANEL_TOP + 20.0;
+                draw_text("Simulated network conditions (F1)", x, y, 20.0, ORANGE);
+            }
+            return;
+        }
+
+        if is_key_pressed(KeyCode::Tab) {
+            self.selected = (self.selected + 1) % PARAMETERS.len();
+        }
+        let mut conditions = self
+            .conditions
+            .lock()
+            .expect("Network conditions lock poisoned.");
+        if is_key_pressed(KeyCode::Backspace) {
+            *conditions = NetworkConditions::default();
+        }
+        if is_key_pressed(KeyCode::Equal) {
+            self.adjust(&mut conditions, true);
+        }
+        if is_key_pressed(KeyCode::Minus) {
+            self.adjust(&mut conditions, false);
+        }
+
+        self.render(&conditions);
+    }
+
+    fn adjust(&self, conditions: &mut NetworkConditions, increase: bool) {
+        let delay = |ms: u32| {
+            if increase {
+                (ms + DELAY_STEP_MS).min(MAX_DELAY_MS)
+            } else {
+                ms.saturating_sub(DELAY_STEP_MS)
+            }
+        };
+        let chance = |chance: f32| {
+            let step = if increase { CHANCE_STEP } else { -CHANCE_STEP };
+            // round to avoid drifting away from multiples of the step
+            ((chance + step).clamp(0.0, 1.0) / CHANCE_STEP).round() * CHANCE_STEP
+        };
+        match self.selected {
+            0 => conditions.latency_ms = delay(conditions.latency_ms),
+            1 => conditions.jitter_ms = delay(conditions.jitter_ms),
+            2 => conditions.loss = chance(conditions.loss),
+            3 => conditions.duplication = chance(conditions.duplication),
+            _ => conditions.reordering = chance(conditions.reordering),
+        }
+    }
+
+    fn render(&self, conditions: &NetworkConditions) {
+        let values = [
+            format!("{} ms", conditions.latency_ms),
+            format!("{} ms", conditions.jitter_ms),
+            format!("{:.0}%", conditions.loss * 100.0),
+            format!("{:.0}%", conditions.duplication * 100.0),
+            format!("{:.0}%", conditions.reordering * 100.0),
+        ];
+
+        let (x, y) = (screen_width() - PANEL_X_FROM_RIGHT, PANEL_TOP);
+        let background = Color::new(0.0, 0.0, 0.0, 0.8);
+        draw_rectangle(x - 10.0, y, PANEL_X_FROM_RIGHT, 190.0, background);
+        draw_text("Network conditions", x, y + 25.0, 30.0, WHITE);
+        for (i, (name, value)) in PARAMETERS.iter().zip(values.iter()).enumerate() {
+            let color = if i == self.selected { YELLOW } else { WHITE };
+            let line = format!("{name}: {value}");
+            draw_text(&line, x, y + 55.0 + i as f32 * 20.0, 25.0, color);
+        }
+        draw_text("TAB select, -/= change", x, y + 160.0, 20.0, GRAY);
+        draw_text("BACKSPACE reset, F1 hide", x, y + 180.0, 20.0, GRAY);
+    }
+}
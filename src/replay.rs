@@ -0,0 +1,395 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use ggrs::{Frame, InputStatus};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::SessionSettings;
+use crate::sim::{Input, State, CHECKSUM_PERIOD};
+
+/// every replay file starts with these bytes
+pub const REPLAY_MAGIC: [u8; 4] = *b"GGRP";
+/// bump this whenever the file layout or the simulation changes in an incompatible way
+pub const REPLAY_VERSION: u32 = 10;
+
+pub const REPLAY_DIR: &str = "replays";
+
+/// during playback, a snapshot of the state is kept every `SNAPSHOT_INTERVAL` frames to seek quickly
+const SNAPSHOT_INTERVAL: Frame = 240;
+
+/// Status of a recorded input. Only confirmed inputs are recorded, so they are never predicted.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RecordedStatus {
+    Confirmed,
+    Disconnected,
+}
+
+/// All confirmed inputs of a single frame.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    pub frame: Frame,
+    pub inputs: Vec<(Input, RecordedStatus)>,
+    /// checksum of the state after advancing this frame, recorded every `CHECKSUM_PERIOD` frames
+    pub checksum: Option<u16>,
+}
+
+impl ReplayFrame {
+    pub fn new(frame: Frame, inputs: &[(Input, InputStatus)], checksum: Option<u16>) -> Self {
+        let inputs = inputs
+            .iter()
+            .map(|(input, status)| match status {
+                InputStatus::Disconnected => (*input, RecordedStatus::Disconnected),
+                // predictions are replaced by confirmed inputs before a frame is confirmed
+                InputStatus::Confirmed | InputStatus::Predicted => {
+                    (*input, RecordedStatus::Confirmed)
+                }
+            })
+            .collect();
+        Self {
+            frame,
+            inputs,
+            checksum,
+        }
+    }
+
+    /// the inputs in the form `State::advance` expects them
+    pub fn inputs(&self) -> Vec<(Input, InputStatus)> {
+        self.inputs
+            .iter()
+            .map(|(input, status)| match status {
+                RecordedStatus::Confirmed => (*input, InputStatus::Confirmed),
+                RecordedStatus::Disconnected => (*input, InputStatus::Disconnected),
+            })
+            .collect()
+    }
+}
+
+/// A replay file is laid out as `REPLAY_MAGIC`, `REPLAY_VERSION`, the `SessionSettings` and the
+/// initial `State`, followed by one `ReplayFrame` per frame, all encoded with bincode.
+pub struct Replay {
+    pub settings: SessionSettings,
+    pub initial_state: State,
+    pub frames: Vec<ReplayFrame>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    NotAReplay,
+    UnsupportedVersion(u32),
+    Corrupt(bincode::Error),
+    MissingFrame(Frame),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "could not read replay: {e}"),
+            ReplayError::NotAReplay => write!(f, "not a replay file"),
+            ReplayError::UnsupportedVersion(version) => write!(
+                f,
+                "replay has version {version}, but only version {REPLAY_VERSION} is supported"
+            ),
+            ReplayError::Corrupt(e) => write!(f, "replay is corrupt: {e}"),
+            ReplayError::MissingFrame(frame) => write!(f, "replay is missing frame {frame}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let mut reader = BufReader::new(File::open(path).map_err(ReplayError::Io)?);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic).map_err(ReplayError::Io)?;
+        if magic != REPLAY_MAGIC {
+            return Err(ReplayError::NotAReplay);
+        }
+        let version: u32 = bincode::deserialize_from(&mut reader).map_err(ReplayError::Corrupt)?;
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let settings = bincode::deserialize_from(&mut reader).map_err(ReplayError::Corrupt)?;
+        let initial_state = bincode::deserialize_from(&mut reader).map_err(ReplayError::Corrupt)?;
+
+        // frames are appended while playing, so a replay simply ends wherever the file ends
+        let mut frames = Vec::new();
+        loop {
+            match bincode::deserialize_from::<_, ReplayFrame>(&mut reader) {
+                Ok(frame) if frame.frame == frames.len() as Frame => frames.push(frame),
+                Ok(_) => return Err(ReplayError::MissingFrame(frames.len() as Frame)),
+                Err(e) => match *e {
+                    bincode::ErrorKind::Io(ref io_err)
+                        if io_err.kind() == io::ErrorKind::UnexpectedEof =>
+                    {
+                        break
+                    }
+                    _ => return Err(ReplayError::Corrupt(e)),
+                },
+            }
+        }
+
+        Ok(Self {
+            settings,
+            initial_state,
+            frames,
+        })
+    }
+}
+
+/// returns the most recently modified replay inside `REPLAY_DIR`
+pub fn latest_replay_path() -> Option<PathBuf> {
+    std::fs::read_dir(REPLAY_DIR)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "ggrs"))
+        .max_by_key(|entry| entry.metadata().and_then(|m| m.modified()).ok())
+        .map(|entry| entry.path())
+}
+
+/// seconds since the unix epoch, used to name files
+pub(crate) fn unix_timestamp() -> u64 {
+    instant::SystemTime::now()
+        .duration_since(instant::SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// returns a new, timestamped path inside `REPLAY_DIR`
+pub fn new_replay_path() -> PathBuf {
+    let timestamp = unix_timestamp();
+    Path::new(REPLAY_DIR).join(format!("replay-{timestamp}.ggrs"))
+}
+
+/// `ReplayRecorder` collects the inputs of every advanced frame and writes them to the replay file
+/// once GGRS confirms them. Frames are appended as soon as they are confirmed, so the replay
+/// survives the window being closed.
+pub struct ReplayRecorder {
+    writer: BufWriter<File>,
+    // inputs and checksums of frames that may still be rolled back
+    pending: BTreeMap<Frame, ReplayFrame>,
+}
+
+impl ReplayRecorder {
+    pub fn create(
+        path: &Path,
+        settings: &SessionSettings,
+        initial_state: &State,
+    ) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&REPLAY_MAGIC)?;
+        bincode::serialize_into(&mut writer, &REPLAY_VERSION).map_err(io::Error::other)?;
+        bincode::serialize_into(&mut writer, settings).map_err(io::Error::other)?;
+        bincode::serialize_into(&mut writer, initial_state).map_err(io::Error::other)?;
+        writer.flush()?;
+
+        Ok(Self {
+            writer,
+            pending: BTreeMap::new(),
+        })
+    }
+
+    /// remembers the inputs of `frame` and the checksum of the resulting state.
+    /// Resimulated frames overwrite what was recorded for them before.
+    pub fn record(&mut self, frame: Frame, inputs: &[(Input, InputStatus)], checksum: u16) {
+        let checksum = ((frame + 1) % CHECKSUM_PERIOD == 0).then_some(checksum);
+        self.pending
+            .insert(frame, ReplayFrame::new(frame, inputs, checksum));
+    }
+
+    /// writes all recorded frames up to and including `confirmed_frame` to the file
+    pub fn confirm(&mut self, confirmed_frame: Frame) -> io::Result<()> {
+        let mut written = false;
+        while let Some(entry) = self.pending.first_entry() {
+            if *entry.key() > confirmed_frame {
+                break;
+            }
+            bincode::serialize_into(&mut self.writer, &entry.remove()).map_err(io::Error::other)?;
+            written = true;
+        }
+        if written {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// The first frame at which the resimulated state did not match the recorded checksum.
+#[derive(Copy, Clone, Debug)]
+pub struct Divergence {
+    pub frame: Frame,
+    pub recorded: u16,
+    pub simulated: u16,
+}
+
+/// `ReplayPlayer` resimulates a `Replay` frame by frame and verifies the recorded checksums.
+pub struct ReplayPlayer {
+    replay: Replay,
+    state: State,
+    // snapshots[i] is the state at frame i * SNAPSHOT_INTERVAL
+    snapshots: Vec<State>,
+    divergence: Option<Divergence>,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        let state = replay.initial_state.clone();
+        Self {
+            snapshots: vec![state.clone()],
+            state,
+            replay,
+            divergence: None,
+        }
+    }
+
+    pub fn settings(&self) -> &SessionSettings {
+        &self.replay.settings
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn frame(&self) -> Frame {
+        self.state.frame
+    }
+
+    /// the frame the state is at after the last recorded inputs
+    pub fn last_frame(&self) -> Frame {
+        self.replay.frames.len() as Frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame() >= self.last_frame()
+    }
+
+    pub fn divergence(&self) -> Option<Divergence> {
+        self.divergence
+    }
+
+    /// advances the state by one recorded frame, returns false at the end of the replay
+    pub fn step(&mut self) -> bool {
+        let Some(recorded) = self.replay.frames.get(self.state.frame as usize) else {
+            return false;
+        };
+        self.state.advance(recorded.inputs());
+
+        if let Some(expected) = recorded.checksum {
+            let actual = self.state.checksum();
+            let earlier_divergence = self.divergence.is_some_and(|d| d.frame <= self.state.frame);
+            if actual != expected && !earlier_divergence {
+                self.divergence = Some(Divergence {
+                    frame: self.state.frame,
+                    recorded: expected,
+                    simulated: actual,
+                });
+            }
+        }
+
+        if self.state.frame == self.snapshots.len() as Frame * SNAPSHOT_INTERVAL {
+            self.snapshots.push(self.state.clone());
+        }
+        true
+    }
+
+    /// moves to the given frame, starting from the closest snapshot before it
+    pub fn seek(&mut self, frame: Frame) {
+        let target = frame.clamp(0, self.last_frame());
+        let snapshot = ((target / SNAPSHOT_INTERVAL) as usize).min(self.snapshots.len() - 1);
+        let snapshot_frame = snapshot as Frame * SNAPSHOT_INTERVAL;
+
+        // only restore the snapshot if it gets us closer than simply stepping forward
+        if self.state.frame > target || self.state.frame < snapshot_frame {
+            self.state = self.snapshots[snapshot].clone();
+        }
+        while self.state.frame < target {
+            self.step();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Map;
+    use crate::sim::{Rules, INPUT_FIRE, INPUT_UP};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ggrs_demo_{}_{name}.ggrs", std::process::id()))
+    }
+
+    fn settings() -> SessionSettings {
+        SessionSettings {
+            num_players: 2,
+            max_prediction_window: 12,
+            input_delay: 2,
+            seed: 42,
+            map: Map::default(),
+            rules: Rules::default(),
+        }
+    }
+
+    #[test]
+    fn recorded_replay_loads_back() {
+        let path = temp_path("round_trip");
+        let settings = settings();
+        let initial_state = State::new(2, settings.seed, &settings.map, settings.rules);
+
+        let mut recorder = ReplayRecorder::create(&path, &settings, &initial_state).unwrap();
+        let mut state = initial_state.clone();
+        for frame in 0..2 * CHECKSUM_PERIOD {
+            let inputs = vec![
+                (Input { inp: INPUT_UP }, InputStatus::Confirmed),
+                (Input { inp: INPUT_FIRE }, InputStatus::Disconnected),
+            ];
+            state.advance(inputs.clone());
+            recorder.record(frame, &inputs, state.checksum());
+        }
+        recorder.confirm(2 * CHECKSUM_PERIOD).unwrap();
+        drop(recorder);
+
+        let replay = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replay.settings, settings);
+        assert_eq!(replay.initial_state.checksum(), initial_state.checksum());
+        assert_eq!(replay.frames.len() as Frame, 2 * CHECKSUM_PERIOD);
+        let last = replay.frames.last().unwrap();
+        assert_eq!(
+            last.inputs,
+            vec![
+                (Input { inp: INPUT_UP }, RecordedStatus::Confirmed),
+                (Input { inp: INPUT_FIRE }, RecordedStatus::Disconnected),
+            ]
+        );
+        assert_eq!(last.checksum, Some(state.checksum()));
+
+        // playing the replay back ends in the recorded state
+        let mut player = ReplayPlayer::new(replay);
+        while player.step() {}
+        assert_eq!(player.state().checksum(), state.checksum());
+        assert!(player.divergence().is_none());
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let path = temp_path("version");
+        let mut bytes = REPLAY_MAGIC.to_vec();
+        bytes.extend(bincode::serialize(&(REPLAY_VERSION + 1)).unwrap());
+        std::fs::write(&path, bytes).unwrap();
+
+        let result = Replay::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            result,
+            Err(ReplayError::UnsupportedVersion(version)) if version == REPLAY_VERSION + 1
+        ));
+    }
+}
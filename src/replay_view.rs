@@ -0,0 +1,160 @@
+use ggrs::Frame;
+use ggrs_demo::replay::ReplayPlayer;
+use ggrs_demo::sim::FPS;
+use instant::{Duration, Instant};
+use macroquad::prelude::*;
+
+use crate::ex_game::{ConnectionStatus, FrameStatus, Game};
+
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 8.0;
+// how far LEFT/RIGHT jump, in frames
+const JUMP_FRAMES: Frame = 300;
+
+// ReplayView plays back a recorded match and lets the user pause, step, change speed and seek
+pub struct ReplayView {
+    player: ReplayPlayer,
+    game: Game,
+    paused: bool,
+    speed: f64,
+    frame_field: String,
+    reported_divergence: bool,
+    last_update: Instant,
+    accumulator: Duration,
+}
+
+impl ReplayView {
+    pub fn new(player: ReplayPlayer) -> Self {
+        let num_players = player.settings().num_players;
+        let mut game = Game::new(player.settings());
+        game.set_connection_status((0..num_players).collect(), ConnectionStatus::Replay);
+        game.set_state(player.state());
+
+        // drop characters typed before, e.g. in the lobby
+        clear_input_queue();
+
+        Self {
+            player,
+            game,
+            paused: false,
+            speed: 1.0,
+            frame_field: String::new(),
+            reported_divergence: false,
+            last_update: Instant::now(),
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    pub fn run(&mut self) {
+        self.handle_keys();
+
+        // get delta time from last iteration and accumulate it, scaled by the playback speed
+        let delta = Instant::now().duration_since(self.last_update);
+        self.last_update = Instant::now();
+        if !self.paused {
+            self.accumulator = self.accumulator.saturating_add(delta.mul_f64(self.speed));
+        }
+
+        // if enough time is accumulated, we run a frame
+        let fps_delta = 1. / FPS as f64;
+        while self.accumulator.as_secs_f64() > fps_delta {
+            self.accumulator = self
+                .accumulator
+                .saturating_sub(Duration::from_secs_f64(fps_delta));
+            if !self.player.step() {
+                self.paused = true;
+                self.accumulator = Duration::ZERO;
+            }
+        }
+
+        self.update_game();
+        self.game.render();
+        self.game.render_debug();
+        self.render_overlay();
+    }
+
+    fn handle_keys(&mut self) {
+        if is_key_pressed(KeyCode::Space) {
+            self.paused = !self.paused;
+            self.accumulator = Duration::ZERO;
+        }
+        if is_key_pressed(KeyCode::Period) && self.paused {
+            self.player.step();
+        }
+        if is_key_pressed(KeyCode::Up) {
+            self.speed = (self.speed * 2.0).min(MAX_SPEED);
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.speed = (self.speed / 2.0).max(MIN_SPEED);
+        }
+        if is_key_pressed(KeyCode::Left) {
+            self.player.seek(self.player.frame() - JUMP_FRAMES);
+        }
+        if is_key_pressed(KeyCode::Right) {
+            self.player.seek(self.player.frame() + JUMP_FRAMES);
+        }
+
+        // type a frame number and press ENTER to jump there
+        while let Some(c) = get_char_pressed() {
+            if c.is_ascii_digit() && self.frame_field.len() < 9 {
+                self.frame_field.push(c);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.frame_field.pop();
+        }
+        if is_key_pressed(KeyCode::Enter) {
+            if let Ok(frame) = self.frame_field.parse() {
+                self.player.seek(frame);
+            }
+            self.frame_field.clear();
+        }
+    }
+
+    fn update_game(&mut self) {
+        self.game.set_state(self.player.state());
+
+        // flag the first frame at which the replay diverged
+        if let Some(divergence) = self.player.divergence() {
+            if !self.reported_divergence {
+                error!(
+                    "Replay diverged at frame {}: recorded checksum {}, simulated checksum {}",
+                    divergence.frame, divergence.recorded, divergence.simulated
+                );
+                self.reported_divergence = true;
+            }
+            self.game.frame_info = FrameStatus::Diverged {
+                frame: divergence.frame,
+                recorded: divergence.recorded,
+                simulated: divergence.simulated,
+            };
+        }
+    }
+
+    fn render_overlay(&self) {
+        let y = screen_height() - 80.0;
+        let state_str = if self.player.is_finished() {
+            "finished"
+        } else if self.paused {
+            "paused"
+        } else {
+            "playing"
+        };
+        let replay_str = format!(
+            "Replay: frame {} / {} ({state_str}, {}x)",
+            self.player.frame(),
+            self.player.last_frame(),
+            self.speed
+        );
+        draw_text(&replay_str, 20.0, y, 30.0, WHITE);
+        let jump_str = format!("Jump to frame: {}", self.frame_field);
+        draw_text(&jump_str, 20.0, y + 20.0, 30.0, WHITE);
+        draw_text(
+            "SPACE pause, . step, UP/DOWN speed, LEFT/RIGHT seek, digits + ENTER jump, ESC leave",
+            20.0,
+            y + 40.0,
+            30.0,
+            WHITE,
+        );
+    }
+}
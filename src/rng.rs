@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+use crate::fixed::{Angle, Fixed};
+
+// any odd number works, this is the default increment of the PCG reference implementation
+const INCREMENT: u64 = 1442695040888963407;
+const MULTIPLIER: u64 = 6364136223846793005;
+
+/// A PCG32 random number generator. It lives inside the `State`, so it is saved, rolled back and
+/// serialized together with everything else, and every peer draws the same numbers.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self { state: 0 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(INCREMENT);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// a number in `0..bound`
+    pub fn below(&mut self, bound: u32) -> u32 {
+        ((self.next_u32() as u64 * bound as u64) >> 32) as u32
+    }
+
+    /// a number in `min..max`
+    pub fn fixed_between(&mut self, min: Fixed, max: Fixed) -> Fixed {
+        Fixed(min.0 + self.below((max.0 - min.0) as u32) as i32)
+    }
+
+    pub fn angle(&mut self) -> Angle {
+        Angle(self.next_u32() as u16)
+    }
+}
+
+/// combines the given values into a seed, the same values always give the same seed
+pub fn seed_from(parts: &[&[u8]]) -> u64 {
+    // FNV-1a
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        for byte in *part {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// a seed for sessions that do not need to agree on it with anyone
+pub fn random_seed() -> u64 {
+    instant::SystemTime::now()
+        .duration_since(instant::SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
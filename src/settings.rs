@@ -0,0 +1,36 @@
+use ggrs::{DesyncDetection, SessionBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::map::Map;
+use crate::sim::{GGRSConfig, Rules, CHECKSUM_PERIOD, FPS};
+
+/// `SessionSettings` holds everything the peers of a session need to agree on before starting.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionSettings {
+    pub num_players: usize,
+    pub max_prediction_window: usize,
+    pub input_delay: usize,
+    // seeds the random number generator of the `State`
+    pub seed: u64,
+    // the arena the session is played in
+    pub map: Map,
+    pub rules: Rules,
+}
+
+impl SessionSettings {
+    /// creates a `SessionBuilder` configured with these settings, players still have to be added
+    pub fn session_builder(&self) -> SessionBuilder<GGRSConfig> {
+        SessionBuilder::<GGRSConfig>::new()
+            .with_num_players(self.num_players)
+            .with_max_prediction_window(self.max_prediction_window)
+            .expect("Invalid prediction window")
+            // the simulation counts speeds and timers in frames, so it only runs at its own rate
+            .with_fps(FPS as usize)
+            .expect("Invalid FPS")
+            .with_input_delay(self.input_delay)
+            // peers compare the checksums of the frames that are also shown on screen
+            .with_desync_detection_mode(DesyncDetection::On {
+                interval: CHECKSUM_PERIOD as u32,
+            })
+    }
+}
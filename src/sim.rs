@@ -0,0 +1,739 @@
+use bytemuck::{Pod, Zeroable};
+use ggrs::{Config, InputStatus};
+use serde::{Deserialize, Serialize};
+
+use crate::fixed::{Angle, Fixed};
+use crate::map::{Map, Obstacle};
+use crate::net::PeerAddr;
+use crate::rng::Rng;
+
+pub const FPS: u64 = 60;
+pub const CHECKSUM_PERIOD: i32 = 100;
+
+pub const ARENA_HEIGHT: Fixed = Fixed::from_int(800);
+pub const ARENA_WIDTH: Fixed = Fixed::from_int(800);
+
+pub const INPUT_UP: u8 = 0b0001;
+pub const INPUT_DOWN: u8 = 0b0010;
+pub const INPUT_LEFT: u8 = 0b0100;
+pub const INPUT_RIGHT: u8 = 0b1000;
+pub const INPUT_FIRE: u8 = 0b1_0000;
+pub const INPUT_REMATCH: u8 = 0b10_0000;
+
+// all physics is fixed-point, so every platform simulates the exact same states
+const MOVEMENT_SPEED: Fixed = Fixed::from_ratio(15, FPS as i32);
+// 2.5 radians per second
+const ROTATION_SPEED: Angle = Angle(435);
+const MAX_SPEED: Fixed = Fixed::from_int(7);
+const FRICTION: Fixed = Fixed::from_ratio(98, 100);
+// ships collide as circles of this radius, roughly the size of the rendered triangle
+pub const SHIP_RADIUS: Fixed = Fixed::from_int(20);
+
+/// the number of bullets that can be in flight at the same time, shared by all players
+pub const MAX_BULLETS: usize = 32;
+pub const MAX_HEALTH: u8 = 5;
+pub const BULLET_SPEED: Fixed = Fixed::from_int(10);
+const BULLET_LIFETIME: u16 = 90;
+// frames between two shots of the same ship
+const FIRE_COOLDOWN: u16 = 15;
+// frames a destroyed ship waits before it spawns again
+const RESPAWN_FRAMES: u16 = 120;
+// destroyed ships respawn at a random spot at least this far from the arena borders
+const RESPAWN_MARGIN: Fixed = Fixed::from_int(100);
+// how often a respawn position is drawn again because it touches an obstacle
+const RESPAWN_ATTEMPTS: usize = 16;
+
+/// the number of power-ups that can lie in the arena at the same time
+pub const MAX_POWER_UPS: usize = 3;
+pub const POWER_UP_RADIUS: Fixed = Fixed::from_int(12);
+/// frames a collected power-up lasts
+pub const POWER_UP_FRAMES: u16 = 8 * FPS as u16;
+// a new power-up appears a random number of frames in this range after the last one
+const POWER_UP_MIN_INTERVAL: u16 = 4 * FPS as u16;
+const POWER_UP_MAX_INTERVAL: u16 = 10 * FPS as u16;
+const SPEED_BOOST: Fixed = Fixed::from_ratio(3, 2);
+const RAPID_FIRE_COOLDOWN: u16 = 5;
+
+/// a round is won by the first player to destroy this many ships
+pub const KILLS_PER_ROUND: u32 = 3;
+/// the match is won by the first player to win this many rounds
+pub const ROUNDS_TO_WIN: u32 = 2;
+pub const COUNTDOWN_FRAMES: u16 = 3 * FPS as u16;
+const ROUND_OVER_FRAMES: u16 = 3 * FPS as u16;
+
+/// The phases of a match. Ships only move and shoot while `Playing`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Phase {
+    Countdown { frames_left: u16 },
+    Playing,
+    RoundOver { winner: usize, frames_left: u16 },
+    // every player has to vote for a rematch to start a new match
+    MatchOver { winner: usize },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PowerUpKind {
+    // faster acceleration and a higher top speed
+    Speed,
+    // bullets do not damage the ship
+    Shield,
+    // a shorter cooldown between shots
+    RapidFire,
+}
+
+// a power-up lying in the arena, waiting to be collected
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PowerUp {
+    // every spawned power-up gets a new id, so it can be followed from the arena to its collector
+    pub id: u32,
+    pub kind: PowerUpKind,
+    pub position: (Fixed, Fixed),
+}
+
+// a power-up collected by a ship
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ActivePowerUp {
+    pub id: u32,
+    pub kind: PowerUpKind,
+    pub frames_left: u16,
+}
+
+/// Optional rules of a session, every peer has to simulate with the same ones.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rules {
+    // ships and bullets leaving the arena reappear on the opposite edge instead of stopping at it
+    pub screen_wrap: bool,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+pub struct Input {
+    pub inp: u8,
+}
+
+/// `GGRSConfig` holds all type parameters for GGRS Sessions
+#[derive(Debug)]
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = Input;
+    type State = State;
+    type Address = PeerAddr;
+}
+
+/// computes the fletcher16 checksum, copied from wikipedia: <https://en.wikipedia.org/wiki/Fletcher%27s_checksum>
+pub fn fletcher16(data: &[u8]) -> u16 {
+    let mut sum1: u16 = 0;
+    let mut sum2: u16 = 0;
+
+    for byte in data {
+        sum1 = (sum1 + *byte as u16) % 255;
+        sum2 = (sum2 + sum1) % 255;
+    }
+
+    (sum2 << 8) | sum1
+}
+
+// a slot of the bullet pool, inactive slots are free to be fired
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bullet {
+    pub active: bool,
+    pub owner: usize,
+    pub position: (Fixed, Fixed),
+    pub velocity: (Fixed, Fixed),
+    // frames until the bullet disappears
+    pub lifetime: u16,
+}
+
+// BoxGameState holds all relevant information about the game state
+#[derive(Clone, Serialize, Deserialize)]
+pub struct State {
+    pub frame: i32,
+    pub num_players: usize,
+    pub positions: Vec<(Fixed, Fixed)>,
+    pub velocities: Vec<(Fixed, Fixed)>,
+    pub rotations: Vec<Angle>,
+    // a ship with zero health is destroyed and waits for its respawn timer
+    pub health: Vec<u8>,
+    pub respawn_timers: Vec<u16>,
+    // frames until the ship can fire again
+    pub cooldowns: Vec<u16>,
+    pub kills: Vec<u32>,
+    pub deaths: Vec<u32>,
+    pub bullets: [Bullet; MAX_BULLETS],
+    pub phase: Phase,
+    pub round_kills: Vec<u32>,
+    pub round_wins: Vec<u32>,
+    pub rematch_votes: Vec<bool>,
+    pub power_ups: Vec<PowerUp>,
+    // the power-ups each ship collected, at most one of each kind
+    pub active_power_ups: Vec<Vec<ActivePowerUp>>,
+    // frames until the next power-up spawns
+    pub power_up_timer: u16,
+    pub next_power_up_id: u32,
+    // all randomness has to come from here, so it is rolled back with the rest of the state
+    pub rng: Rng,
+    // the obstacles never change, but keeping them here makes the state self-contained
+    pub obstacles: Vec<Obstacle>,
+    pub rules: Rules,
+}
+
+impl State {
+    /// `seed`, `map` and `rules` have to be the same for every peer of a session
+    pub fn new(num_players: usize, seed: u64, map: &Map, rules: Rules) -> Self {
+        let mut positions = Vec::new();
+        let mut rotations = Vec::new();
+
+        for i in 0..num_players {
+            let (position, rotation) = spawn_point(i, num_players);
+            positions.push(position);
+            rotations.push(rotation);
+        }
+
+        Self {
+            frame: 0,
+            num_players,
+            positions,
+            velocities: vec![(Fixed::ZERO, Fixed::ZERO); num_players],
+            rotations,
+            health: vec![MAX_HEALTH; num_players],
+            respawn_timers: vec![0; num_players],
+            cooldowns: vec![0; num_players],
+            kills: vec![0; num_players],
+            deaths: vec![0; num_players],
+            bullets: [Bullet::default(); MAX_BULLETS],
+            phase: Phase::Countdown {
+                frames_left: COUNTDOWN_FRAMES,
+            },
+            round_kills: vec![0; num_players],
+            round_wins: vec![0; num_players],
+            rematch_votes: vec![false; num_players],
+            power_ups: Vec::new(),
+            active_power_ups: vec![Vec::new(); num_players],
+            power_up_timer: POWER_UP_MIN_INTERVAL,
+            next_power_up_id: 0,
+            rng: Rng::new(seed),
+            obstacles: map.obstacles.clone(),
+            rules,
+        }
+    }
+
+    pub fn is_alive(&self, player: usize) -> bool {
+        self.health[player] > 0
+    }
+
+    pub fn has_power_up(&self, player: usize, kind: PowerUpKind) -> bool {
+        self.active_power_ups[player]
+            .iter()
+            .any(|power_up| power_up.kind == kind)
+    }
+
+    // serializes the state and computes its fletcher16 checksum
+    pub fn checksum(&self) -> u16 {
+        let buffer = bincode::serialize(self).unwrap();
+        fletcher16(&buffer)
+    }
+
+    pub fn advance(&mut self, inputs: Vec<(Input, InputStatus)>) {
+        // increase the frame counter
+        self.frame += 1;
+
+        // outside of rounds, the ships are frozen
+        match self.phase {
+            Phase::Countdown { frames_left } => {
+                self.phase = match frames_left {
+                    0 | 1 => Phase::Playing,
+                    _ => Phase::Countdown {
+                        frames_left: frames_left - 1,
+                    },
+                };
+                return;
+            }
+            Phase::RoundOver {
+                winner,
+                frames_left,
+            } => {
+                match frames_left {
+                    0 | 1 => self.start_round(),
+                    _ => {
+                        self.phase = Phase::RoundOver {
+                            winner,
+                            frames_left: frames_left - 1,
+                        }
+                    }
+                }
+                return;
+            }
+            Phase::MatchOver { .. } => {
+                self.vote_rematch(&inputs);
+                return;
+            }
+            Phase::Playing => (),
+        }
+
+        for (i, (input, status)) in inputs.iter().enumerate() {
+            // destroyed ships only wait for their respawn
+            if !self.is_alive(i) {
+                self.respawn_timers[i] -= 1;
+                if self.respawn_timers[i] == 0 {
+                    self.respawn(i);
+                }
+                continue;
+            }
+
+            // get input of that player
+            let input = match status {
+                InputStatus::Confirmed => input.inp,
+                InputStatus::Predicted => input.inp,
+                InputStatus::Disconnected => INPUT_LEFT, // disconnected players spin
+            };
+
+            // old values
+            let (old_x, old_y) = self.positions[i];
+            let (old_vel_x, old_vel_y) = self.velocities[i];
+            let mut rot = self.rotations[i];
+
+            // slow down
+            let mut vel_x = old_vel_x * FRICTION;
+            let mut vel_y = old_vel_y * FRICTION;
+
+            // thrust
+            let movement_speed = if self.has_power_up(i, PowerUpKind::Speed) {
+                MOVEMENT_SPEED * SPEED_BOOST
+            } else {
+                MOVEMENT_SPEED
+            };
+            if input & INPUT_UP != 0 && input & INPUT_DOWN == 0 {
+                vel_x += movement_speed * rot.cos();
+                vel_y += movement_speed * rot.sin();
+            }
+            // break
+            if input & INPUT_UP == 0 && input & INPUT_DOWN != 0 {
+                vel_x -= movement_speed * rot.cos();
+                vel_y -= movement_speed * rot.sin();
+            }
+            // turn left, angles wrap around on their own
+            if input & INPUT_LEFT != 0 && input & INPUT_RIGHT == 0 {
+                rot = rot - ROTATION_SPEED;
+            }
+            // turn right
+            if input & INPUT_LEFT == 0 && input & INPUT_RIGHT != 0 {
+                rot = rot + ROTATION_SPEED;
+            }
+
+            // limit speed
+            let max_speed = if self.has_power_up(i, PowerUpKind::Speed) {
+                MAX_SPEED * SPEED_BOOST
+            } else {
+                MAX_SPEED
+            };
+            let magnitude = Fixed::hypot(vel_x, vel_y);
+            if magnitude > max_speed {
+                vel_x = (vel_x * max_speed) / magnitude;
+                vel_y = (vel_y * max_speed) / magnitude;
+            }
+
+            // compute new position and constrain players to canvas borders
+            let (x, y) = self.confine_to_arena(old_x + vel_x, old_y + vel_y);
+
+            // update all state
+            self.positions[i] = (x, y);
+            self.velocities[i] = (vel_x, vel_y);
+            self.rotations[i] = rot;
+
+            // fire
+            self.cooldowns[i] = self.cooldowns[i].saturating_sub(1);
+            if input & INPUT_FIRE != 0 && self.cooldowns[i] == 0 {
+                self.fire(i);
+            }
+        }
+
+        self.collide_ships();
+        self.collide_obstacles();
+        self.move_bullets();
+        self.update_power_ups();
+        self.check_round_over();
+    }
+
+    // ends the round once a player destroyed enough ships, and the match once a player won enough rounds
+    fn check_round_over(&mut self) {
+        let Some(winner) = (0..self.num_players).find(|&i| self.round_kills[i] >= KILLS_PER_ROUND)
+        else {
+            return;
+        };
+        self.round_wins[winner] += 1;
+        self.phase = if self.round_wins[winner] >= ROUNDS_TO_WIN {
+            Phase::MatchOver { winner }
+        } else {
+            Phase::RoundOver {
+                winner,
+                frames_left: ROUND_OVER_FRAMES,
+            }
+        };
+    }
+
+    // puts all ships back to their spawn points, the scores are kept
+    fn start_round(&mut self) {
+        for i in 0..self.num_players {
+            let (position, rotation) = spawn_point(i, self.num_players);
+            self.reset_ship(i, position, rotation);
+            self.respawn_timers[i] = 0;
+            self.round_kills[i] = 0;
+        }
+        self.bullets = [Bullet::default(); MAX_BULLETS];
+        self.power_ups.clear();
+        self.power_up_timer = POWER_UP_MIN_INTERVAL;
+        self.phase = Phase::Countdown {
+            frames_left: COUNTDOWN_FRAMES,
+        };
+    }
+
+    // starts a new match once every player asked for it, disconnected players do not get a say
+    fn vote_rematch(&mut self, inputs: &[(Input, InputStatus)]) {
+        for (i, (input, status)) in inputs.iter().enumerate() {
+            if *status == InputStatus::Disconnected || input.inp & INPUT_REMATCH != 0 {
+                self.rematch_votes[i] = true;
+            }
+        }
+        if self.rematch_votes.iter().all(|vote| *vote) {
+            // the frame keeps counting, GGRS does not know about matches
+            let (frame, rng) = (self.frame, self.rng);
+            let map = Map {
+                name: String::new(),
+                obstacles: std::mem::take(&mut self.obstacles),
+            };
+            *self = State::new(self.num_players, 0, &map, self.rules);
+            self.frame = frame;
+            self.rng = rng;
+        }
+    }
+
+    fn respawn(&mut self, player: usize) {
+        let position = self.random_position();
+        let rotation = self.rng.angle();
+        self.reset_ship(player, position, rotation);
+    }
+
+    // a random spot away from the arena borders, which does not touch an obstacle if possible
+    fn random_position(&mut self) -> (Fixed, Fixed) {
+        for _ in 0..RESPAWN_ATTEMPTS {
+            let x = self
+                .rng
+                .fixed_between(RESPAWN_MARGIN, ARENA_WIDTH - RESPAWN_MARGIN);
+            let y = self
+                .rng
+                .fixed_between(RESPAWN_MARGIN, ARENA_HEIGHT - RESPAWN_MARGIN);
+            if !self.touches_obstacle(x, y) {
+                return (x, y);
+            }
+        }
+        self.free_position()
+    }
+
+    // the first spot on a grid over the whole arena that does not touch an obstacle, for crowded
+    // maps where random spots keep missing, the center if the obstacles cover everything
+    fn free_position(&self) -> (Fixed, Fixed) {
+        let mut y = SHIP_RADIUS;
+        while y <= ARENA_HEIGHT - SHIP_RADIUS {
+            let mut x = SHIP_RADIUS;
+            while x <= ARENA_WIDTH - SHIP_RADIUS {
+                if !self.touches_obstacle(x, y) {
+                    return (x, y);
+                }
+                x += SHIP_RADIUS;
+            }
+            y += SHIP_RADIUS;
+        }
+        let two = Fixed::from_int(2);
+        (ARENA_WIDTH / two, ARENA_HEIGHT / two)
+    }
+
+    fn touches_obstacle(&self, x: Fixed, y: Fixed) -> bool {
+        self.obstacles.iter().any(|obstacle| {
+            let ((min_x, min_y), (max_x, max_y)) = (obstacle.min(), obstacle.max());
+            let (dx, dy) = (x - x.clamp(min_x, max_x), y - y.clamp(min_y, max_y));
+            Fixed::hypot(dx, dy) < SHIP_RADIUS
+        })
+    }
+
+    fn reset_ship(&mut self, player: usize, position: (Fixed, Fixed), rotation: Angle) {
+        self.positions[player] = position;
+        self.velocities[player] = (Fixed::ZERO, Fixed::ZERO);
+        self.rotations[player] = rotation;
+        self.health[player] = MAX_HEALTH;
+        self.cooldowns[player] = 0;
+        self.active_power_ups[player].clear();
+    }
+
+    // spawns a bullet at the nose of the ship, if there is a free slot in the pool
+    fn fire(&mut self, player: usize) {
+        let Some(bullet) = self.bullets.iter_mut().find(|bullet| !bullet.active) else {
+            return;
+        };
+        let (x, y) = self.positions[player];
+        let (vel_x, vel_y) = self.velocities[player];
+        let rot = self.rotations[player];
+        let (dir_x, dir_y) = (rot.cos(), rot.sin());
+        *bullet = Bullet {
+            active: true,
+            owner: player,
+            position: (x + dir_x * SHIP_RADIUS, y + dir_y * SHIP_RADIUS),
+            velocity: (vel_x + dir_x * BULLET_SPEED, vel_y + dir_y * BULLET_SPEED),
+            lifetime: BULLET_LIFETIME,
+        };
+        self.cooldowns[player] = if self.has_power_up(player, PowerUpKind::RapidFire) {
+            RAPID_FIRE_COOLDOWN
+        } else {
+            FIRE_COOLDOWN
+        };
+    }
+
+    // moves all bullets and lets them hit the first ship they overlap with
+    fn move_bullets(&mut self) {
+        for b in 0..MAX_BULLETS {
+            let mut bullet = self.bullets[b];
+            if !bullet.active {
+                continue;
+            }
+
+            let (x, y) = bullet.position;
+            let (vel_x, vel_y) = bullet.velocity;
+            let (x, y) = (x + vel_x, y + vel_y);
+            // without screen wrap, bullets leaving the arena are gone
+            let in_arena = self.rules.screen_wrap
+                || ((Fixed::ZERO..=ARENA_WIDTH).contains(&x)
+                    && (Fixed::ZERO..=ARENA_HEIGHT).contains(&y));
+            let (x, y) = self.confine_to_arena(x, y);
+            bullet.position = (x, y);
+            bullet.lifetime -= 1;
+            let blocked = self
+                .obstacles
+                .iter()
+                .any(|obstacle| obstacle.contains(x, y));
+            bullet.active = bullet.lifetime > 0 && in_arena && !blocked;
+
+            if bullet.active {
+                let target = (0..self.num_players).find(|&i| {
+                    let (dx, dy) = self.offset((x, y), self.positions[i]);
+                    i != bullet.owner && self.is_alive(i) && Fixed::hypot(dx, dy) < SHIP_RADIUS
+                });
+                if let Some(target) = target {
+                    bullet.active = false;
+                    self.hit(target, bullet.owner);
+                }
+            }
+
+            self.bullets[b] = bullet;
+        }
+    }
+
+    fn hit(&mut self, target: usize, shooter: usize) {
+        if self.has_power_up(target, PowerUpKind::Shield) {
+            return;
+        }
+        self.health[target] -= 1;
+        if self.health[target] == 0 {
+            self.respawn_timers[target] = RESPAWN_FRAMES;
+            self.deaths[target] += 1;
+            self.kills[shooter] += 1;
+            self.round_kills[shooter] += 1;
+        }
+    }
+
+    // spawns new power-ups, lets ships collect them and counts down the collected ones
+    fn update_power_ups(&mut self) {
+        for power_ups in &mut self.active_power_ups {
+            for power_up in power_ups.iter_mut() {
+                power_up.frames_left -= 1;
+            }
+            power_ups.retain(|power_up| power_up.frames_left > 0);
+        }
+
+        // the lowest player handle wins, if several ships reach a power-up on the same frame
+        let mut p = 0;
+        while p < self.power_ups.len() {
+            let power_up = self.power_ups[p];
+            let collector = (0..self.num_players).find(|&i| {
+                let (dx, dy) = self.offset(power_up.position, self.positions[i]);
+                self.is_alive(i) && Fixed::hypot(dx, dy) < SHIP_RADIUS + POWER_UP_RADIUS
+            });
+            let Some(collector) = collector else {
+                p += 1;
+                continue;
+            };
+            self.power_ups.remove(p);
+            // collecting a kind again starts it over
+            let active = &mut self.active_power_ups[collector];
+            active.retain(|active| active.kind != power_up.kind);
+            active.push(ActivePowerUp {
+                id: power_up.id,
+                kind: power_up.kind,
+                frames_left: POWER_UP_FRAMES,
+            });
+        }
+
+        // the timer waits while the arena is full
+        if self.power_ups.len() < MAX_POWER_UPS {
+            self.power_up_timer -= 1;
+        }
+        if self.power_up_timer == 0 {
+            let kind = match self.rng.below(3) {
+                0 => PowerUpKind::Speed,
+                1 => PowerUpKind::Shield,
+                _ => PowerUpKind::RapidFire,
+            };
+            let position = self.random_position();
+            self.power_ups.push(PowerUp {
+                id: self.next_power_up_id,
+                kind,
+                position,
+            });
+            self.next_power_up_id += 1;
+            let interval = POWER_UP_MAX_INTERVAL - POWER_UP_MIN_INTERVAL;
+            self.power_up_timer = POWER_UP_MIN_INTERVAL + self.rng.below(interval as u32) as u16;
+        }
+    }
+
+    // bounces overlapping ships off each other. Ships have equal mass, so an elastic collision
+    // exchanges their velocities along the line between them.
+    // Pairs are always resolved in the same order, so every peer gets the same result.
+    fn collide_ships(&mut self) {
+        let min_distance = SHIP_RADIUS + SHIP_RADIUS;
+        let two = Fixed::from_int(2);
+
+        for i in 0..self.num_players {
+            for j in i + 1..self.num_players {
+                if !self.is_alive(i) || !self.is_alive(j) {
+                    continue;
+                }
+                let (x_i, y_i) = self.positions[i];
+                let (x_j, y_j) = self.positions[j];
+                let (dx, dy) = self.offset(self.positions[i], self.positions[j]);
+                let distance = Fixed::hypot(dx, dy);
+                if distance >= min_distance {
+                    continue;
+                }
+
+                // collision normal pointing from i to j, ships on the same spot are pushed apart sideways
+                let (nx, ny) = if distance == Fixed::ZERO {
+                    (Fixed::ONE, Fixed::ZERO)
+                } else {
+                    (dx / distance, dy / distance)
+                };
+
+                // exchange the velocity components along the normal, if the ships approach each other
+                let (vx_i, vy_i) = self.velocities[i];
+                let (vx_j, vy_j) = self.velocities[j];
+                let approach = (vx_i - vx_j) * nx + (vy_i - vy_j) * ny;
+                if approach > Fixed::ZERO {
+                    self.velocities[i] = (vx_i - approach * nx, vy_i - approach * ny);
+                    self.velocities[j] = (vx_j + approach * nx, vy_j + approach * ny);
+                }
+
+                // push both ships out of each other, but not out of the arena
+                let push = (min_distance - distance) / two;
+                self.positions[i] = self.confine_to_arena(x_i - push * nx, y_i - push * ny);
+                self.positions[j] = self.confine_to_arena(x_j + push * nx, y_j + push * ny);
+            }
+        }
+    }
+
+    // pushes ships out of obstacles and bounces them off, obstacles do not move
+    fn collide_obstacles(&mut self) {
+        for i in 0..self.num_players {
+            if !self.is_alive(i) {
+                continue;
+            }
+            for obstacle in &self.obstacles {
+                let (x, y) = self.positions[i];
+                let ((min_x, min_y), (max_x, max_y)) = (obstacle.min(), obstacle.max());
+
+                // the point of the obstacle closest to the center of the ship
+                let (dx, dy) = (x - x.clamp(min_x, max_x), y - y.clamp(min_y, max_y));
+                let distance = Fixed::hypot(dx, dy);
+                if distance >= SHIP_RADIUS {
+                    continue;
+                }
+
+                // normal pointing out of the obstacle and how far the ship has to move along it
+                let (nx, ny, depth) = if distance == Fixed::ZERO {
+                    // the center is inside, leave through the closest side that has room for the
+                    // ship, sides along the arena border would push it out and back into the wall
+                    let sides = [
+                        (x - min_x, -Fixed::ONE, Fixed::ZERO, min_x >= SHIP_RADIUS),
+                        (
+                            max_x - x,
+                            Fixed::ONE,
+                            Fixed::ZERO,
+                            max_x + SHIP_RADIUS <= ARENA_WIDTH,
+                        ),
+                        (y - min_y, Fixed::ZERO, -Fixed::ONE, min_y >= SHIP_RADIUS),
+                        (
+                            max_y - y,
+                            Fixed::ZERO,
+                            Fixed::ONE,
+                            max_y + SHIP_RADIUS <= ARENA_HEIGHT,
+                        ),
+                    ];
+                    let closest = |open_only: bool| {
+                        sides
+                            .into_iter()
+                            .filter(|side| !open_only || self.rules.screen_wrap || side.3)
+                            .min_by_key(|(inside, _, _, _)| *inside)
+                    };
+                    // an obstacle spanning the whole arena leaves no choice
+                    let (inside, nx, ny, _) = closest(true)
+                        .or_else(|| closest(false))
+                        .expect("There are four sides.");
+                    (nx, ny, inside + SHIP_RADIUS)
+                } else {
+                    (dx / distance, dy / distance, SHIP_RADIUS - distance)
+                };
+                self.positions[i] = self.confine_to_arena(x + depth * nx, y + depth * ny);
+
+                // reflect the velocity, if the ship moves into the obstacle
+                let (vel_x, vel_y) = self.velocities[i];
+                let into = vel_x * nx + vel_y * ny;
+                if into < Fixed::ZERO {
+                    let two = Fixed::from_int(2);
+                    self.velocities[i] = (vel_x - two * into * nx, vel_y - two * into * ny);
+                }
+            }
+        }
+    }
+
+    // stops positions at the arena borders, or wraps them around with screen wrap
+    fn confine_to_arena(&self, x: Fixed, y: Fixed) -> (Fixed, Fixed) {
+        if self.rules.screen_wrap {
+            (x.rem_euclid(ARENA_WIDTH), y.rem_euclid(ARENA_HEIGHT))
+        } else {
+            (
+                x.clamp(Fixed::ZERO, ARENA_WIDTH),
+                y.clamp(Fixed::ZERO, ARENA_HEIGHT),
+            )
+        }
+    }
+
+    /// the vector from one position to another, with screen wrap the shorter way across an edge
+    pub fn offset(&self, from: (Fixed, Fixed), to: (Fixed, Fixed)) -> (Fixed, Fixed) {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        if !self.rules.screen_wrap {
+            return (dx, dy);
+        }
+        let two = Fixed::from_int(2);
+        let (half_width, half_height) = (ARENA_WIDTH / two, ARENA_HEIGHT / two);
+        (
+            (dx + half_width).rem_euclid(ARENA_WIDTH) - half_width,
+            (dy + half_height).rem_euclid(ARENA_HEIGHT) - half_height,
+        )
+    }
+}
+
+// ships start evenly spread on a circle around the center, facing it
+fn spawn_point(player: usize, num_players: usize) -> ((Fixed, Fixed), Angle) {
+    let r = ARENA_WIDTH / Fixed::from_int(4);
+    let two = Fixed::from_int(2);
+    let rot = Angle::from_turns(player as u32, num_players as u32);
+    let x = ARENA_WIDTH / two + r * rot.cos();
+    let y = ARENA_HEIGHT / two + r * rot.sin();
+    ((x, y), rot + Angle::HALF_TURN)
+}
@@ -0,0 +1,161 @@
+use ggrs_demo::sim::{
+    Input, INPUT_DOWN, INPUT_FIRE, INPUT_LEFT, INPUT_REMATCH, INPUT_RIGHT, INPUT_UP,
+};
+use macroquad::prelude::*;
+
+// the knob of the joystick has to be dragged this far before it steers
+const JOYSTICK_DEADZONE: f32 = 20.0;
+// dragging further than this does not steer any harder, it is also the size of the drawn joystick
+const JOYSTICK_RADIUS: f32 = 80.0;
+// how far the knob has to point along an axis to press that direction, as a fraction of its distance
+const DIRECTION_THRESHOLD: f32 = 0.4;
+const FIRE_BUTTON_RADIUS: f32 = 70.0;
+const REMATCH_BUTTON_RADIUS: f32 = 35.0;
+const BUTTON_MARGIN: f32 = 40.0;
+
+// TouchControls turns touches into the input of the first local player, for phones and tablets.
+// A touch on the left half of the screen becomes a joystick centered where it started,
+// a touch on the right half fires, except on the small rematch button.
+pub struct TouchControls {
+    // the touch steering the joystick, with the position it started at and the current one
+    joystick: Option<(u64, Vec2, Vec2)>,
+    fire: bool,
+    rematch: bool,
+    // the controls are only drawn once the screen has been touched
+    used: bool,
+}
+
+impl TouchControls {
+    pub fn new() -> Self {
+        Self {
+            joystick: None,
+            fire: false,
+            rematch: false,
+            used: false,
+        }
+    }
+
+    // follows the touches of this frame, has to be called every frame
+    pub fn update(&mut self) {
+        let touches = touches();
+        self.used |= !touches.is_empty();
+        self.fire = false;
+        self.rematch = false;
+
+        for touch in &touches {
+            let ended = matches!(touch.phase, TouchPhase::Ended | TouchPhase::Cancelled);
+            match self.joystick {
+                Some((id, origin, _)) if id == touch.id => {
+                    self.joystick = (!ended).then_some((id, origin, touch.position));
+                    continue;
+                }
+                None if touch.phase == TouchPhase::Started
+                    && touch.position.x < screen_width() / 2. =>
+                {
+                    self.joystick = Some((touch.id, touch.position, touch.position));
+                    continue;
+                }
+                _ => (),
+            }
+            if ended || touch.position.x < screen_width() / 2. {
+                continue;
+            }
+            if touch.position.distance(rematch_button_center()) < REMATCH_BUTTON_RADIUS {
+                self.rematch = true;
+            } else {
+                self.fire = true;
+            }
+        }
+
+        // a touch can disappear without an end event, e.g. when the page loses focus
+        if let Some((id, ..)) = self.joystick {
+            if !touches.iter().any(|touch| touch.id == id) {
+                self.joystick = None;
+            }
+        }
+    }
+
+    // only the first local player can be played by touch
+    pub fn input(&self, local_player: usize) -> Input {
+        let mut inp: u8 = 0;
+        if local_player != 0 {
+            return Input { inp };
+        }
+
+        if let Some((_, origin, position)) = self.joystick {
+            let offset = position - origin;
+            let distance = offset.length();
+            if distance > JOYSTICK_DEADZONE {
+                let direction = offset / distance;
+                // screen coordinates grow downwards
+                if direction.y < -DIRECTION_THRESHOLD {
+                    inp |= INPUT_UP;
+                }
+                if direction.x < -DIRECTION_THRESHOLD {
+                    inp |= INPUT_LEFT;
+                }
+                if direction.y > DIRECTION_THRESHOLD {
+                    inp |= INPUT_DOWN;
+                }
+                if direction.x > DIRECTION_THRESHOLD {
+                    inp |= INPUT_RIGHT;
+                }
+            }
+        }
+        if self.fire {
+            inp |= INPUT_FIRE;
+        }
+        if self.rematch {
+            inp |= INPUT_REMATCH;
+        }
+
+        Input { inp }
+    }
+
+    pub fn render(&self) {
+        if !self.used {
+            return;
+        }
+        let translucent = Color::new(1.0, 1.0, 1.0, 0.3);
+
+        if let Some((_, origin, position)) = self.joystick {
+            let offset = position - origin;
+            let knob = origin + offset.clamp_length_max(JOYSTICK_RADIUS);
+            draw_circle_lines(origin.x, origin.y, JOYSTICK_RADIUS, 3.0, translucent);
+            draw_circle(knob.x, knob.y, JOYSTICK_RADIUS / 3., translucent);
+        } else {
+            draw_text(
+                "drag here to steer",
+                BUTTON_MARGIN,
+                screen_height() - BUTTON_MARGIN,
+                30.0,
+                translucent,
+            );
+        }
+
+        let fire = fire_button_center();
+        let fire_color = if self.fire { RED } else { translucent };
+        draw_circle(fire.x, fire.y, FIRE_BUTTON_RADIUS, fire_color);
+        draw_text("FIRE", fire.x - 30., fire.y + 10., 30.0, WHITE);
+
+        let rematch = rematch_button_center();
+        let rematch_color = if self.rematch { GREEN } else { translucent };
+        draw_circle(rematch.x, rematch.y, REMATCH_BUTTON_RADIUS, rematch_color);
+        draw_text("again", rematch.x - 28., rematch.y + 7., 24.0, WHITE);
+    }
+}
+
+fn fire_button_center() -> Vec2 {
+    vec2(
+        screen_width() - BUTTON_MARGIN - FIRE_BUTTON_RADIUS,
+        screen_height() - BUTTON_MARGIN - FIRE_BUTTON_RADIUS,
+    )
+}
+
+fn rematch_button_center() -> Vec2 {
+    let fire = fire_button_center();
+    vec2(
+        fire.x,
+        fire.y - FIRE_BUTTON_RADIUS - BUTTON_MARGIN - REMATCH_BUTTON_RADIUS,
+    )
+}
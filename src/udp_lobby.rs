@@ -0,0 +1,335 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+use ggrs::{PlayerHandle, PlayerType};
+use ggrs_demo::net::PeerAddr;
+use ggrs_demo::rng::random_seed;
+use ggrs_demo::settings::SessionSettings;
+use ggrs_demo::sim::Rules;
+use instant::{Duration, Instant};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_UDP_PORT: u16 = 7000;
+
+// every lobby datagram starts with these bytes, so it is not mistaken for a GGRS packet
+const UDP_LOBBY_MAGIC: [u8; 4] = *b"GGUL";
+// join and start messages are repeated until they are answered, since UDP may drop them
+const RESEND_INTERVAL: Duration = Duration::from_millis(250);
+const RECV_BUFFER_SIZE: usize = 1024;
+
+#[derive(Serialize, Deserialize)]
+enum UdpLobbyMessage {
+    Join {
+        num_players: usize,
+        map_hash: u64,
+        rules: Rules,
+    },
+    // tells a joiner its handle and the addresses of all players, `None` being the host
+    Start {
+        handle: PlayerHandle,
+        players: Vec<Option<SocketAddr>>,
+        seed: u64,
+    },
+    // confirms the start message, the joiner starts its session right after sending it
+    Ready,
+    Reject {
+        reason: String,
+    },
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum UdpRole {
+    Host { port: u16 },
+    Join { host: SocketAddr },
+}
+
+// everything needed to start a GGRS session over UDP
+pub struct UdpPlayers {
+    pub port: u16,
+    pub players: Vec<PlayerType<PeerAddr>>,
+    // chosen by the host
+    pub seed: u64,
+}
+
+// UdpLobby gathers the players for a direct UDP session without a signaling server.
+// Joiners announce themselves to the host, which assigns the handles once everyone is there.
+pub struct UdpLobby {
+    socket: UdpSocket,
+    role: UdpRole,
+    num_players: usize,
+    // only players with the same map and rules may join
+    map_name: String,
+    map_hash: u64,
+    rules: Rules,
+    // addresses of the players that joined the host, in the order they joined
+    joined: Vec<SocketAddr>,
+    // the seed of the host, chosen once everyone joined
+    seed: Option<u64>,
+    // joiners that have not confirmed the start message yet
+    unconfirmed: Vec<SocketAddr>,
+    last_sent: Option<Instant>,
+    error: Option<String>,
+    buffer: [u8; RECV_BUFFER_SIZE],
+}
+
+impl UdpLobby {
+    pub fn new(role: UdpRole, settings: &SessionSettings) -> io::Result<Self> {
+        // joiners let the OS pick a free port
+        let port = match role {
+            UdpRole::Host { port } => port,
+            UdpRole::Join { .. } => 0,
+        };
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        socket.set_nonblocking(true)?;
+        info!("Bound UDP lobby to {}", socket.local_addr()?);
+
+        Ok(Self {
+            socket,
+            role,
+            num_players: settings.num_players,
+            map_name: settings.map.name.clone(),
+            map_hash: settings.map.hash(),
+            rules: settings.rules,
+            joined: Vec::new(),
+            seed: None,
+            unconfirmed: Vec::new(),
+            last_sent: None,
+            error: None,
+            buffer: [0; RECV_BUFFER_SIZE],
+        })
+    }
+
+    // returns the players once the session can start
+    pub fn update(&mut self) -> Option<UdpPlayers> {
+        let resend_due = self.last_sent.is_none_or(|t| t.elapsed() > RESEND_INTERVAL);
+        match self.role {
+            UdpRole::Join { host } if resend_due && self.error.is_none() => {
+                self.send(
+                    &UdpLobbyMessage::Join {
+                        num_players: self.num_players,
+                        map_hash: self.map_hash,
+                        rules: self.rules,
+                    },
+                    host,
+                );
+                self.last_sent = Some(Instant::now());
+            }
+            UdpRole::Host { .. } if resend_due && self.seed.is_some() => {
+                self.send_start();
+                self.last_sent = Some(Instant::now());
+            }
+            _ => (),
+        }
+
+        while let Some((msg, addr)) = self.receive() {
+            match self.role {
+                UdpRole::Host { .. } => self.handle_as_host(msg, addr),
+                UdpRole::Join { host } => {
+                    let players = self.handle_as_joiner(msg, addr, host);
+                    if players.is_some() {
+                        return players;
+                    }
+                }
+            }
+        }
+
+        // the host starts its session once every joiner got the start message
+        match (self.role, self.seed) {
+            (UdpRole::Host { .. }, Some(seed)) if self.unconfirmed.is_empty() => {
+                let mut players = vec![PlayerType::Local];
+                players.extend(
+                    self.joined
+                        .iter()
+                        .map(|addr| PlayerType::Remote(PeerAddr::Udp(*addr))),
+                );
+                Some(UdpPlayers {
+                    port: self.local_port(),
+                    players,
+                    seed,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn handle_as_host(&mut self, msg: UdpLobbyMessage, addr: SocketAddr) {
+        let (num_players, map_hash, rules) = match msg {
+            UdpLobbyMessage::Join {
+                num_players,
+                map_hash,
+                rules,
+            } => (num_players, map_hash, rules),
+            UdpLobbyMessage::Ready => {
+                self.confirm(addr);
+                return;
+            }
+            UdpLobbyMessage::Start { .. } | UdpLobbyMessage::Reject { .. } => return,
+        };
+        if self.seed.is_some() {
+            // joiners that are waiting for the start message get it with the next resend
+            if !self.joined.contains(&addr) {
+                let reason = "The host has already started the match.".to_owned();
+                self.send(&UdpLobbyMessage::Reject { reason }, addr);
+            }
+            return;
+        }
+        if num_players != self.num_players {
+            let reason = format!("The host plays with {} players.", self.num_players);
+            self.send(&UdpLobbyMessage::Reject { reason }, addr);
+            return;
+        }
+        if map_hash != self.map_hash {
+            let reason = format!("The host plays on map {}.", self.map_name);
+            self.send(&UdpLobbyMessage::Reject { reason }, addr);
+            return;
+        }
+        if rules != self.rules {
+            let reason = format!("The host plays with {:?}.", self.rules);
+            self.send(&UdpLobbyMessage::Reject { reason }, addr);
+            return;
+        }
+        if !self.joined.contains(&addr) {
+            info!("{} joined", addr);
+            self.joined.push(addr);
+        }
+        if self.joined.len() + 1 < self.num_players {
+            return;
+        }
+
+        // everyone is here, tell the joiners who plays with which handle
+        self.seed = Some(random_seed());
+        self.unconfirmed = self.joined.clone();
+        self.send_start();
+        self.last_sent = Some(Instant::now());
+    }
+
+    // sends the start message to every joiner that has not confirmed it yet
+    fn send_start(&self) {
+        let seed = self.seed.expect("Should only start once everyone joined.");
+        let mut players = vec![None];
+        players.extend(self.joined.iter().copied().map(Some));
+        for (i, joiner) in self.joined.iter().enumerate() {
+            if !self.unconfirmed.contains(joiner) {
+                continue;
+            }
+            let start = UdpLobbyMessage::Start {
+                handle: i + 1,
+                players: players.clone(),
+                seed,
+            };
+            self.send(&start, *joiner);
+        }
+    }
+
+    fn confirm(&mut self, addr: SocketAddr) {
+        if self.unconfirmed.contains(&addr) {
+            info!("{} is ready", addr);
+            self.unconfirmed.retain(|joiner| *joiner != addr);
+        }
+    }
+
+    fn handle_as_joiner(
+        &mut self,
+        msg: UdpLobbyMessage,
+        addr: SocketAddr,
+        host: SocketAddr,
+    ) -> Option<UdpPlayers> {
+        if addr != host {
+            return None;
+        }
+        match msg {
+            UdpLobbyMessage::Start {
+                handle,
+                players,
+                seed,
+            } => {
+                self.send(&UdpLobbyMessage::Ready, host);
+                let players = players
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, player)| match player {
+                        _ if i == handle => PlayerType::Local,
+                        Some(addr) => PlayerType::Remote(PeerAddr::Udp(addr)),
+                        None => PlayerType::Remote(PeerAddr::Udp(host)),
+                    })
+                    .collect();
+                Some(UdpPlayers {
+                    port: self.local_port(),
+                    players,
+                    seed,
+                })
+            }
+            UdpLobbyMessage::Reject { reason } => {
+                warn!("Host rejected us: {}", reason);
+                self.error = Some(reason);
+                None
+            }
+            UdpLobbyMessage::Join { .. } | UdpLobbyMessage::Ready => None,
+        }
+    }
+
+    fn local_port(&self) -> u16 {
+        self.socket
+            .local_addr()
+            .expect("Socket should be bound.")
+            .port()
+    }
+
+    fn send(&self, msg: &UdpLobbyMessage, addr: SocketAddr) {
+        let mut packet = UDP_LOBBY_MAGIC.to_vec();
+        packet.extend(bincode::serialize(msg).unwrap());
+        if let Err(e) = self.socket.send_to(&packet, addr) {
+            warn!("Could not send to {}: {}", addr, e);
+        }
+    }
+
+    fn receive(&mut self) -> Option<(UdpLobbyMessage, SocketAddr)> {
+        loop {
+            let (len, addr) = match self.socket.recv_from(&mut self.buffer) {
+                Ok(received) => received,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return None,
+                // datagram sockets sometimes get this after sending to a closed port
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(e) => {
+                    warn!("Could not receive: {}", e);
+                    return None;
+                }
+            };
+            let packet = &self.buffer[..len];
+            if !packet.starts_with(&UDP_LOBBY_MAGIC) {
+                // a joiner whose session already sends GGRS packets got the start message,
+                // even if its confirmation was lost
+                self.confirm(addr);
+                continue;
+            }
+            if let Ok(msg) = bincode::deserialize(&packet[UDP_LOBBY_MAGIC.len()..]) {
+                return Some((msg, addr));
+            }
+        }
+    }
+
+    pub fn render(&self) {
+        let info_str = match self.role {
+            UdpRole::Host { .. } if self.seed.is_some() => format!(
+                "Starting, waiting for {} player(s) to confirm...",
+                self.unconfirmed.len()
+            ),
+            UdpRole::Host { port } => format!(
+                "Hosting on UDP port {port}, waiting for {} more player(s)...",
+                self.num_players - 1 - self.joined.len()
+            ),
+            UdpRole::Join { host } => format!("Joining {host}..."),
+        };
+        draw_text(&info_str, 20.0, 20.0, 30.0, WHITE);
+
+        for (i, addr) in self.joined.iter().enumerate() {
+            let joined_str = format!("Player {}: {addr}", i + 1);
+            draw_text(&joined_str, 20.0, 60.0 + i as f32 * 20.0, 30.0, WHITE);
+        }
+
+        if let Some(error) = &self.error {
+            draw_text(error, 20.0, 40.0, 30.0, RED);
+        }
+    }
+}